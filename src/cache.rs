@@ -0,0 +1,253 @@
+use std::env;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use anyhow::{Context, Result};
+
+/// How many directories deep tiles are filed: by satellite, sector, product, timestamp and zoom.
+const TILE_DEPTH: usize = 5;
+
+/// A persistent, size-limited store of raw (still encoded) imagery tiles.
+pub struct TileCache {
+    root: PathBuf,
+    max_bytes: u64,
+}
+
+/// Everything that uniquely identifies a single tile.
+#[derive(Debug, Clone, Copy)]
+pub struct TileKey<'a> {
+    pub satellite: &'a str,
+    pub sector: &'a str,
+    pub product: &'a str,
+    pub timestamp: u64,
+    pub zoom: u32,
+    pub row: u32,
+    pub column: u32,
+}
+
+impl TileKey<'_> {
+    fn path(&self, root: &Path) -> PathBuf {
+        root.join(self.satellite)
+            .join(self.sector)
+            .join(self.product)
+            .join(self.timestamp.to_string())
+            .join(format!("{:02}", self.zoom))
+            .join(format!("{:03}_{:03}.png", self.row, self.column))
+    }
+}
+
+impl TileCache {
+    pub fn new(root: PathBuf, max_bytes: u64) -> Self {
+        Self {
+            root,
+            max_bytes,
+        }
+    }
+
+    /// Returns the cached bytes of the given tile, if present.
+    pub fn get(&self, key: &TileKey) -> Option<Vec<u8>> {
+        match fs::read(key.path(&self.root)) {
+            Ok(bytes) => Some(bytes),
+            Err(err) if err.kind() == ErrorKind::NotFound => None,
+            Err(err) => {
+                log::warn!("Failed to read cached tile {key:?}: {err}");
+                None
+            }
+        }
+    }
+
+    /// Stores the bytes of the given tile.
+    pub fn put(&self, key: &TileKey, bytes: &[u8]) -> Result<()> {
+        let path = key.path(&self.root);
+        let parent = path.parent().expect("Tile paths should always have a parent");
+
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create cache directory {parent:?}"))?;
+
+        // Write to a temporary file first, so a crash can never leave a truncated tile behind.
+        let temp = path.with_extension("part");
+        fs::write(&temp, bytes)?;
+        fs::rename(&temp, &path)?;
+
+        Ok(())
+    }
+
+    /// Deletes the least recently written tiles until the cache fits within its size limit.
+    ///
+    /// Only files laid out like tiles are counted (or deleted), so pointing the cache at a
+    /// directory that holds anything else can't cost that anything.
+    pub fn evict(&self) -> Result<()> {
+        let mut files = Vec::new();
+        walk(&self.root, 0, &mut files)?;
+
+        let mut total: u64 = files.iter().map(|(_, size, _)| size).sum();
+
+        if total <= self.max_bytes {
+            return Ok(());
+        }
+
+        log::info!(
+            "Tile cache is {:.2}MiB (limit {:.2}MiB), evicting...",
+            total as f64 / 1048576.0,
+            self.max_bytes as f64 / 1048576.0
+        );
+
+        files.sort_unstable_by_key(|(_, _, modified)| *modified);
+
+        for (path, size, _) in files {
+            if total <= self.max_bytes {
+                break;
+            }
+
+            fs::remove_file(&path)?;
+            total -= size;
+
+            // Clean up any directories that are now empty, stopping at the first that isn't.
+            for dir in path.ancestors().skip(1).take_while(|dir| *dir != self.root) {
+                if fs::remove_dir(dir).is_err() {
+                    break;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// Recursively collects the path, size and modification time of every tile (or partially written
+// tile) under `dir`, which is `depth` directories below the cache's root.
+fn walk(dir: &Path, depth: usize, files: &mut Vec<(PathBuf, u64, SystemTime)>) -> Result<()> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(()),
+        Err(err) => return Err(err.into()),
+    };
+
+    for entry in entries {
+        let entry = entry?;
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        // Symbolic links are never followed (or deleted.)
+        let kind = entry.file_type()?;
+
+        if depth < TILE_DEPTH {
+            if kind.is_dir() && is_tile_dir(&name, depth) {
+                walk(&entry.path(), depth + 1, files)?;
+            }
+        } else if kind.is_file() && is_tile_file(&name) {
+            let meta = entry.metadata()?;
+            files.push((entry.path(), meta.len(), meta.modified()?));
+        }
+    }
+
+    Ok(())
+}
+
+/// Whether a directory `depth` levels below the root could hold tiles.
+fn is_tile_dir(name: &str, depth: usize) -> bool {
+    let digits = |len: Option<usize>| {
+        !name.is_empty() && name.bytes().all(|b| b.is_ascii_digit()) && len.map_or(true, |len| name.len() == len)
+    };
+
+    match depth {
+        // Timestamps, then zoom levels.
+        3 => digits(None),
+        4 => digits(Some(2)),
+        _ => true,
+    }
+}
+
+/// Whether a file is named like a tile (`NNN_NNN.png`) or one being written (`NNN_NNN.part`).
+fn is_tile_file(name: &str) -> bool {
+    let Some((stem, "png" | "part")) = name.rsplit_once('.') else {
+        return false;
+    };
+
+    stem.split_once('_').is_some_and(|(row, column)| {
+        [row, column]
+            .iter()
+            .all(|n| n.len() == 3 && n.bytes().all(|b| b.is_ascii_digit()))
+    })
+}
+
+/// The "satpaper" directory inside the platform's per-user cache directory.
+pub fn default_dir() -> Option<PathBuf> {
+    let home = || env::var_os("HOME").map(PathBuf::from);
+
+    let base = match env::consts::OS {
+        "windows" => env::var_os("LOCALAPPDATA").map(PathBuf::from),
+        "macos" => home().map(|home| home.join("Library/Caches")),
+        _ => env::var_os("XDG_CACHE_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| home().map(|home| home.join(".cache"))),
+    };
+
+    base.map(|base| base.join("satpaper"))
+}
+
+#[test]
+fn test_tile_cache() -> Result<()> {
+    let root = env::temp_dir().join(format!("satpaper-cache-test-{}", std::process::id()));
+    let cache = TileCache::new(root.clone(), 8);
+
+    let key = |column| TileKey {
+        satellite: "goes-19",
+        sector: "full_disk",
+        product: "geocolor",
+        timestamp: 20261017120000,
+        zoom: 2,
+        row: 0,
+        column,
+    };
+
+    assert_eq!(cache.get(&key(0)), None);
+
+    cache.put(&key(0), b"12345")?;
+    assert_eq!(cache.get(&key(0)).as_deref(), Some(&b"12345"[..]));
+
+    // Make sure the second tile is strictly newer, even on coarse filesystem clocks.
+    std::thread::sleep(std::time::Duration::from_millis(20));
+    cache.put(&key(1), b"6789")?;
+    cache.evict()?;
+
+    assert_eq!(cache.get(&key(0)), None);
+    assert_eq!(cache.get(&key(1)).as_deref(), Some(&b"6789"[..]));
+
+    // Anything that isn't laid out like a tile is neither counted nor deleted.
+    let strays = [
+        root.join("photo.png"),
+        root.join("goes-19/full_disk/geocolor/20261017120000/02/notes.txt"),
+        root.join("goes-19/full_disk/geocolor/latest/02/000_000.png"),
+    ];
+
+    for stray in &strays {
+        fs::create_dir_all(stray.parent().unwrap())?;
+        fs::write(stray, b"not a tile, but much larger than the cache")?;
+    }
+
+    cache.evict()?;
+
+    assert_eq!(cache.get(&key(1)).as_deref(), Some(&b"6789"[..]));
+    assert!(strays.iter().all(|stray| stray.exists()));
+
+    fs::remove_dir_all(root)?;
+
+    Ok(())
+}
+
+#[test]
+fn test_is_tile() {
+    assert!(is_tile_file("000_012.png"));
+    assert!(is_tile_file("003_001.part"));
+    assert!(!is_tile_file("000_012.jpg"));
+    assert!(!is_tile_file("0_12.png"));
+    assert!(!is_tile_file("holiday.png"));
+
+    assert!(is_tile_dir("20261017120000", 3));
+    assert!(!is_tile_dir("Pictures", 3));
+    assert!(is_tile_dir("02", 4));
+    assert!(!is_tile_dir("2", 4));
+}
@@ -0,0 +1,538 @@
+use std::fmt;
+use std::marker::PhantomData;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+use anyhow::{Result, Context};
+use fimg::{Image as Img, scale::Lanczos3};
+use rayon::prelude::*;
+
+use crate::config::DiskDetection;
+use crate::disk::{self, Circle};
+use crate::effects;
+use crate::quality::Glitch;
+use crate::source::{Geometry, ImagerySource, Shape};
+
+use super::{
+    Config,
+    OUTPUT_NAME
+};
+
+/// rgb all the way down
+pub type Image<T> = Img<T, 3>;
+
+/// Copies the `width` by `height` region with its top-left corner at (x, y) out of `src`.
+/// 
+/// Panics if the region does not fit inside `src`.
+pub fn crop(src: Image<&[u8]>, x: u32, y: u32, width: u32, height: u32) -> Image<Box<[u8]>> {
+    assert!(x + width <= src.width() && y + height <= src.height());
+
+    let src_stride = src.width() as usize * 3;
+    let dst_stride = width as usize * 3;
+    let offset = x as usize * 3;
+
+    let buf: Box<[u8]> = src
+        .bytes()
+        .chunks_exact(src_stride)
+        .skip(y as usize)
+        .take(height as usize)
+        .flat_map(|line| &line[offset..offset + dst_stride])
+        .copied()
+        .collect();
+
+    Image::build(width, height).buf(buf)
+}
+
+/// Copies `src` into `dst` with its top-left corner at (x, y).
+/// 
+/// Unlike `OverlayAt` (which assumes square overlays), this handles images of any shape.
+/// Panics if `src` does not fit inside `dst` at the given position.
+pub fn blit(dst: &mut Image<Box<[u8]>>, src: Image<&[u8]>, x: u32, y: u32) {
+    assert!(x + src.width() <= dst.width() && y + src.height() <= dst.height());
+
+    let dst_stride = dst.width() as usize * 3;
+    let src_stride = src.width() as usize * 3;
+    let offset = x as usize * 3;
+
+    // SAFETY: we only write whole pixels, within the bounds asserted above.
+    let buf = unsafe { dst.buffer_mut() };
+
+    for (row, line) in src.bytes().chunks_exact(src_stride).enumerate() {
+        let start = (y as usize + row) * dst_stride + offset;
+        buf[start..start + src_stride].copy_from_slice(line);
+    }
+}
+
+/// A shared view of an image that several threads can blit into at once, without a lock.
+pub struct Canvas<'a> {
+    ptr: *mut u8,
+    width: u32,
+    height: u32,
+    _image: PhantomData<&'a mut [u8]>,
+}
+
+// SAFETY: the canvas is only ever written through `blit`, whose callers guarantee writes never overlap.
+unsafe impl Send for Canvas<'_> {}
+unsafe impl Sync for Canvas<'_> {}
+
+impl<'a> Canvas<'a> {
+    pub fn new(image: &'a mut Image<Box<[u8]>>) -> Self {
+        let (width, height) = (image.width(), image.height());
+
+        Self {
+            // SAFETY: the pointer is only used to write whole pixels, within the image's bounds.
+            ptr: unsafe { image.buffer_mut() }.as_mut_ptr(),
+            width,
+            height,
+            _image: PhantomData,
+        }
+    }
+
+    /// Copies `src` onto the canvas with its top-left corner at (x, y), like [`blit`].
+    ///
+    /// Panics if `src` does not fit inside the canvas at the given position.
+    ///
+    /// # Safety
+    /// No other thread may be blitting into an overlapping region at the same time.
+    pub unsafe fn blit(&self, src: Image<&[u8]>, x: u32, y: u32) {
+        assert!(x + src.width() <= self.width && y + src.height() <= self.height);
+
+        let dst_stride = self.width as usize * 3;
+        let src_stride = src.width() as usize * 3;
+        let offset = x as usize * 3;
+
+        for (row, line) in src.bytes().chunks_exact(src_stride).enumerate() {
+            let start = (y as usize + row) * dst_stride + offset;
+
+            std::slice::from_raw_parts_mut(self.ptr.add(start), src_stride).copy_from_slice(line);
+        }
+    }
+}
+
+/// How long each stage of some work took, for benchmark-style logging.
+pub struct Timings {
+    last: Instant,
+    stages: Vec<(&'static str, Duration)>,
+}
+
+impl Timings {
+    pub fn start() -> Self {
+        Self {
+            last: Instant::now(),
+            stages: Vec::new(),
+        }
+    }
+
+    /// Marks the end of a stage, which started when the previous one ended.
+    pub fn lap(&mut self, stage: &'static str) {
+        let now = Instant::now();
+
+        self.stages.push((stage, now - self.last));
+        self.last = now;
+    }
+}
+
+impl fmt::Display for Timings {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let total: Duration = self.stages.iter().map(|(_, time)| *time).sum();
+
+        for (stage, time) in &self.stages {
+            write!(f, "{stage} {time:.2?}, ")?;
+        }
+
+        write!(f, "total {total:.2?}")
+    }
+}
+
+pub fn composite_latest_image(config: &Config, source: &dyn ImagerySource, frame: u64) -> Result<bool> {
+    render_or_fallback(config, source, frame)
+        .map(|image| {
+            let start = Instant::now();
+
+            image.save(
+                config.target_path.join(OUTPUT_NAME)
+            );
+
+            log::info!("Output saved in {:.2?}.", start.elapsed());
+
+            true
+        })
+        .or_else(|err| {
+            log::error!("Failed to download source image: {err}");
+            log::error!("Composition aborted; waiting until next go round.");
+            Ok(false)
+        })
+}
+
+/// Renders the given frame, falling back on older ones (newest first) if it turns out to be glitched.
+fn render_or_fallback(config: &Config, source: &dyn ImagerySource, frame: u64) -> Result<Image<Box<[u8]>>> {
+    let err = match render(config, source, frame) {
+        Err(err) if err.is::<Glitch>() && config.fallback_frames > 0 => err,
+        result => return result,
+    };
+
+    log::warn!("{err}");
+
+    let older = source.frames_before(frame, config.fallback_frames)?;
+
+    if older.is_empty() {
+        log::warn!("There are no frames from before {frame} to fall back on.");
+    }
+
+    for older in older {
+        log::warn!("Falling back to frame {older}...");
+
+        match render(config, source, older) {
+            Err(err) if err.is::<Glitch>() => log::warn!("{err}"),
+            result => return result,
+        }
+    }
+
+    Err(err.context(format!("Frame {frame} and its fallbacks are all glitched")))
+}
+
+/// Fetches the given frame from the source and composites it into a wallpaper-sized image.
+pub fn render(config: &Config, source: &dyn ImagerySource, frame: u64) -> Result<Image<Box<[u8]>>> {
+    let geometry = source.geometry();
+    let (native_x, native_y) = geometry.native_size();
+
+    log::debug!("Source native size: {native_x}x{native_y}");
+
+    let mut timings = Timings::start();
+
+    let (width, height) = match geometry.shape {
+        Shape::Disk => (config.disk(), config.disk()),
+        Shape::Rectangle => cover(config, geometry),
+    };
+
+    let image = source.fetch(frame, width, height)?;
+    timings.lap("fetch");
+
+    let image = match geometry.shape {
+        Shape::Disk => composite(config, image)?,
+        Shape::Rectangle => fill(config, image),
+    };
+    timings.lap("composite");
+
+    log::info!("Render timings: {timings}");
+
+    Ok(image)
+}
+
+fn composite(config: &Config, source: Image<Box<[u8]>>) -> Result<Image<Box<[u8]>>> {
+    log::info!("Compositing...");
+
+    let (offset_x, offset_y) = config.disk_position();
+
+    // Effects are drawn around the disk, so it has to be cut out even on a plain black background.
+    let has_effects = config.glow.is_some() || config.shadow.is_some();
+
+    let composite = if config.background_image.is_some() || has_effects {
+        static BG: OnceLock<Image<Box<[u8]>>> = OnceLock::new();
+
+        let mut bg = match &config.background_image {
+            Some(path) => BG.get_or_try_init(|| {
+                use image::io::Reader;
+
+                let image = Reader::open(path)
+                    .context("Failed to open background image at path {path:?}")?
+                    .decode()
+                    .context("Failed to load background image - corrupt or unsupported?")?
+                    .into_rgb8();
+
+                let mut image = Image::build(image.width(), image.height()).buf(image.into_vec().into_boxed_slice());
+
+                if image.width() != config.resolution_x || 
+                   image.height() != config.resolution_y 
+                {
+                    log::info!("Resizing background image to fit...");
+
+                    image = image.scale::<Lanczos3>(config.resolution_x, config.resolution_y);
+                }
+
+                anyhow::Ok(image)
+            })?.clone(),
+            None => Image::alloc(config.resolution_x, config.resolution_y).boxed(),
+        };
+
+        log::info!("Compositing source into destination...");
+
+        let disk = find_disk(config, source.as_ref());
+        // The same disk, in the wallpaper's coordinates.
+        let placed = Circle {
+            x: disk.x + offset_x as f64,
+            y: disk.y + offset_y as f64,
+            ..disk
+        };
+
+        if let Some(opacity) = config.shadow {
+            effects::shadow(bg.as_mut(), placed, placed.radius * config.shadow_size / 100.0, opacity);
+        }
+
+        if let Some(color) = config.glow {
+            effects::glow(bg.as_mut(), placed, color, placed.radius * config.glow_size / 100.0);
+        }
+
+        cutout_disk(
+            bg.as_mut(),
+            source.as_ref(),
+            disk,
+            config.feather,
+            offset_x,
+            offset_y
+        );
+
+        bg
+    }
+    else {
+        let mut behind = Image::alloc(config.resolution_x, config.resolution_y).boxed();
+
+        place(&mut behind, source.as_ref(), offset_x, offset_y);
+
+        behind
+    };
+    
+    log::info!("Compositing complete.");
+
+    Ok(composite)
+}
+
+/// Copies whatever part of `src` lands on `dst` with its top-left corner at (x, y), which may be off its edges.
+fn place(dst: &mut Image<Box<[u8]>>, src: Image<&[u8]>, x: i64, y: i64) {
+    let visible = |offset: i64, size: u32, bound: u32| {
+        let start = (-offset).clamp(0, i64::from(size));
+        let end = (i64::from(bound) - offset).clamp(0, i64::from(size));
+
+        (start as u32, end.max(start) as u32)
+    };
+
+    let (left, right) = visible(x, src.width(), dst.width());
+    let (top, bottom) = visible(y, src.height(), dst.height());
+
+    if left == right || top == bottom {
+        return;
+    }
+
+    let part = crop(src, left, top, right - left, bottom - top);
+    blit(dst, part.as_ref(), (x + i64::from(left)) as u32, (y + i64::from(top)) as u32);
+}
+
+/// How far (as a fraction of the expected radius) a fitted disk may be off before it's distrusted.
+const MAX_FIT_ERROR: f64 = 0.1;
+
+/// Locates the Earth's disk in the (disk-sized) source image.
+fn find_disk(config: &Config, earth: Image<&[u8]>) -> Circle {
+    let expected = config.satellite.projection().disk(earth.width(), earth.height());
+
+    let fitted = match config.disk_detection {
+        DiskDetection::Geometry => return expected,
+        DiskDetection::Fit => disk::fit(earth, config.edge_threshold),
+    };
+
+    match fitted {
+        Some(circle) if (circle.radius - expected.radius).abs() <= expected.radius * MAX_FIT_ERROR => {
+            log::debug!("Fitted disk: {circle:?} (expected {expected:?})");
+            circle
+        }
+        Some(circle) => {
+            log::warn!("Fitted disk {circle:?} is far from the expected {expected:?}; using the expected disk instead.");
+            expected
+        }
+        None => {
+            log::warn!("Could not find the edge of the disk; using the satellite's geometry instead.");
+            expected
+        }
+    }
+}
+
+/// Draws the pixels of `earth` that lie inside `disk` onto `bg`, with the top-left corner of `earth` at the given offset
+/// (which may put part of it off the edges of `bg`.)
+///
+/// The disk fades out over the `feather` pixels (never less than one) just inside its edge, so it's
+/// anti-aliased rather than stair-stepped - and black space around the source never bleeds onto the background.
+fn cutout_disk(
+    mut bg: Image<&mut [u8]>,
+    earth: Image<&[u8]>,
+    disk: Circle,
+    feather: f64,
+    offset_x: i64,
+    offset_y: i64
+) {
+    log::debug!("Starting cutout process...");
+
+    let feather = feather.max(1.0);
+    let outer = disk.radius;
+    let inner = (disk.radius - feather).max(0.0);
+
+    // Only the columns of the earth that land on the background are drawn.
+    let x_min = (-offset_x).max(0) as f64;
+    let x_max = (earth.width() as f64 - 1.0).min((i64::from(bg.width()) - 1 - offset_x) as f64);
+    let bg_stride = bg.width() as usize * 3;
+    let earth_stride = earth.width() as usize * 3;
+    let earth_height = i64::from(earth.height());
+
+    // The (inclusive) range of columns within `radius` of the disk's center on a row `dy` from it.
+    let span = |radius: f64, dy: f64| {
+        let room = radius * radius - dy * dy;
+
+        if room <= 0.0 {
+            return None;
+        }
+
+        let half = room.sqrt();
+        let left = (disk.x - half).ceil().max(x_min);
+        let right = (disk.x + half).floor().min(x_max);
+
+        (left <= right).then_some((left as usize, right as usize))
+    };
+
+    // SAFETY: we only write whole pixels, within the background's bounds.
+    let rows = unsafe { bg.buffer_mut() }
+        .par_chunks_exact_mut(bg_stride)
+        .enumerate()
+        .filter_map(|(y, bg_row)| {
+            let y = y as i64 - offset_y;
+            (0..earth_height).contains(&y).then_some((y as usize, bg_row))
+        });
+
+    // Each row of the disk is a single contiguous span, so only the pixels near its ends need blending.
+    rows.for_each(|(y, bg_row)| {
+        let dy = y as f64 - disk.y;
+
+        let Some((left, right)) = span(outer, dy) else {
+            return;
+        };
+
+        let earth_row = &earth.bytes()[y * earth_stride..(y + 1) * earth_stride];
+        let at = |x: usize| (x as i64 + offset_x) as usize * 3;
+        let (solid_left, solid_right) = span(inner, dy).unwrap_or((right + 1, right));
+
+        // overlay the earth
+        if solid_left <= solid_right {
+            bg_row[at(solid_left)..at(solid_right) + 3]
+                .copy_from_slice(&earth_row[solid_left * 3..(solid_right + 1) * 3]);
+        }
+
+        for x in (left..solid_left).chain(solid_right + 1..=right) {
+            let coverage = ((outer - (x as f64 - disk.x).hypot(dy)) / feather).clamp(0.0, 1.0);
+
+            for (dst, &src) in bg_row[at(x)..at(x) + 3].iter_mut().zip(&earth_row[x * 3..x * 3 + 3]) {
+                *dst = (f64::from(*dst) * (1.0 - coverage) + f64::from(src) * coverage).round() as u8;
+            }
+        }
+    });
+}
+
+/// Computes the smallest size (rounded up to whole tiles) that preserves the source's
+/// aspect ratio while covering the entire wallpaper.
+fn cover(config: &Config, geometry: Geometry) -> (u32, u32) {
+    let (native_x, native_y) = geometry.native_size();
+
+    let scale = f64::max(
+        config.resolution_x as f64 / native_x as f64,
+        config.resolution_y as f64 / native_y as f64,
+    );
+
+    let round = |dim: u32| {
+        ((dim as f64 * scale).ceil() as u32).next_multiple_of(geometry.tile_count)
+    };
+
+    (round(native_x), round(native_y))
+}
+
+// Fills the wallpaper with a rectangular sector, cropping off whatever overhangs the edges.
+fn fill(config: &Config, source: Image<Box<[u8]>>) -> Image<Box<[u8]>> {
+    log::info!("Cropping sector to fit...");
+
+    let composite = crop(
+        source.as_ref(),
+        (source.width() - config.resolution_x) / 2,
+        (source.height() - config.resolution_y) / 2,
+        config.resolution_x,
+        config.resolution_y,
+    );
+
+    log::info!("Compositing complete.");
+
+    composite
+}
+
+#[test]
+fn test_blit_crop_roundtrip() {
+    let tile: Box<[u8]> = (0..2 * 3 * 3).map(|n| n as u8 + 1).collect();
+    let tile = Image::build(2, 3).buf(tile);
+
+    let mut canvas = Image::alloc(5, 4).boxed();
+    blit(&mut canvas, tile.as_ref(), 3, 1);
+
+    assert_eq!(unsafe { canvas.pixel(0, 0) }, [0; 3]);
+    assert_eq!(crop(canvas.as_ref(), 3, 1, 2, 3).bytes(), tile.bytes());
+}
+
+#[test]
+fn test_canvas_blit() {
+    let tile = |n: u8| Image::build(2, 2).buf(vec![n; 2 * 2 * 3].into_boxed_slice());
+
+    let mut expected = Image::alloc(4, 2).boxed();
+    blit(&mut expected, tile(1).as_ref(), 0, 0);
+    blit(&mut expected, tile(2).as_ref(), 2, 0);
+
+    let mut image = Image::alloc(4, 2).boxed();
+    let canvas = Canvas::new(&mut image);
+
+    rayon::join(
+        || unsafe { canvas.blit(tile(1).as_ref(), 0, 0) },
+        || unsafe { canvas.blit(tile(2).as_ref(), 2, 0) },
+    );
+
+    assert_eq!(image.bytes(), expected.bytes());
+}
+
+#[test]
+fn test_cutout_disk() {
+    let size = 41u32;
+    let disk = Circle { x: 21.3, y: 19.6, radius: 17.5 };
+
+    let earth = Image::build(size, size).buf(vec![201; (size * size * 3) as usize].into_boxed_slice());
+
+    // Including placements that hang off the top-left and bottom-right edges.
+    for (offset_x, offset_y) in [(7, 3), (-15, -4), (40, 30)] {
+        for feather in [1.0, 4.0] {
+            let mut bg = Image::build(60, 50).buf(vec![1; 60 * 50 * 3].into_boxed_slice());
+            cutout_disk(bg.as_mut(), earth.as_ref(), disk, feather, offset_x, offset_y);
+
+            for y in 0..50 {
+                for x in 0..60 {
+                    let (dx, dy) = (x as f64 - offset_x as f64 - disk.x, y as f64 - offset_y as f64 - disk.y);
+                    let dist = dx.hypot(dy);
+                    let [px, ..] = unsafe { bg.pixel(x, y) };
+
+                    if dist <= disk.radius - feather {
+                        assert_eq!(px, 201, "pixel ({x}, {y})");
+                    } else if dist >= disk.radius {
+                        assert_eq!(px, 1, "pixel ({x}, {y})");
+                    } else {
+                        let expected = 1.0 + 200.0 * (disk.radius - dist) / feather;
+                        assert!((px as f64 - expected).abs() <= 1.0, "pixel ({x}, {y}) is {px}, expected {expected}");
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn test_place() {
+    let src: Box<[u8]> = (0..4 * 4 * 3).map(|n| n as u8 + 1).collect();
+    let src = Image::build(4, 4).buf(src);
+
+    let mut dst = Image::alloc(3, 3).boxed();
+    place(&mut dst, src.as_ref(), -2, 1);
+
+    assert_eq!(unsafe { dst.pixel(0, 0) }, [0; 3]);
+    assert_eq!(crop(dst.as_ref(), 0, 1, 2, 2).bytes(), crop(src.as_ref(), 2, 0, 2, 2).bytes());
+    assert_eq!(unsafe { dst.pixel(2, 1) }, [0; 3]);
+
+    // Entirely off the edge.
+    place(&mut dst, src.as_ref(), 10, 10);
+}
@@ -1,7 +1,11 @@
 use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
 
+use anyhow::{bail, Result};
 use clap::{Parser, ValueEnum};
-use fimg::Image;
+
+use crate::projection::{Bounds, Projection, Sweep};
+use crate::time;
 
 #[derive(Debug, Clone, Parser)]
 #[command(author, version, about, long_about = None)]
@@ -28,11 +32,23 @@ pub struct Config {
     #[arg(short = 'y', long, env = "SATPAPER_RESOLUTION_Y")]
     pub resolution_y: u32,
     /// The size of the "disk" (Earth) relative to the generated wallpaper's
-    /// smaller dimension.
+    /// smaller dimension (or whichever is chosen with --disk-scale.)
     /// 
     /// Values in the 90-95 range are the best if you want maximum detail.
     #[arg(short, long, value_parser = clap::value_parser!(u32).range(1..=100), env = "SATPAPER_DISK_SIZE")]
     pub disk_size: u32,
+    /// What the disk size is relative to.
+    #[arg(long, env = "SATPAPER_DISK_SCALE", value_enum, default_value_t = DiskScale::Smaller)]
+    pub disk_scale: DiskScale,
+    /// Where on the wallpaper the disk is placed.
+    #[arg(long, env = "SATPAPER_ALIGN", value_enum, default_value_t = Align::Center)]
+    pub align: Align,
+    /// How far to move the disk from its aligned position, as `X,Y`.
+    /// 
+    /// Each component is either in pixels or a percentage of the wallpaper's width
+    /// (or height), like `-200,0` or `-10%,5%`. Positive values move right and down.
+    #[arg(long, env = "SATPAPER_OFFSET", value_parser = parse_offset, allow_hyphen_values = true)]
+    pub offset: Option<Offset>,
     /// Where generated wallpapers should be saved.
     /// 
     /// Satpaper will output to a file called "satpaper_latest.png" at this path.
@@ -60,8 +76,243 @@ pub struct Config {
     /// but Satpaper will resize the image to fit if need be.
     #[arg(short, long, env = "SATPAPER_BACKGROUND_IMAGE")]
     pub background_image: Option<PathBuf>,
+    /// How to find the edge of the Earth's disk in full-disk imagery.
+    #[arg(long, env = "SATPAPER_DISK_DETECTION", value_enum, default_value_t = DiskDetection::Fit)]
+    pub disk_detection: DiskDetection,
+    /// How bright a pixel must be (in any channel, from 0 to 255) to count as part of
+    /// the Earth when fitting the disk.
+    /// 
+    /// Raise this if noise in space throws off the fit; lower it if a dark night side does.
+    #[arg(long, env = "SATPAPER_EDGE_THRESHOLD", default_value_t = 4)]
+    pub edge_threshold: u8,
+    /// How many pixels the edge of the Earth's disk fades out over when using a background image.
+    /// 
+    /// The edge is always anti-aliased over at least one pixel; larger values give a softer edge.
+    #[arg(long, env = "SATPAPER_FEATHER", value_parser = parse_pixels, default_value_t = 1.0)]
+    pub feather: f64,
+    /// Surround the Earth with an atmospheric glow of this color, like `#4d8fff`.
+    /// 
+    /// The glow is brightest (exactly this color) at the edge of the disk, so darker
+    /// colors give a fainter glow.
+    #[arg(long, env = "SATPAPER_GLOW", value_parser = parse_color)]
+    pub glow: Option<[u8; 3]>,
+    /// How far the glow extends beyond the edge of the disk, as a percentage of its radius.
+    #[arg(long, env = "SATPAPER_GLOW_SIZE", value_parser = parse_pixels, default_value_t = 6.0)]
+    pub glow_size: f64,
+    /// Cast a soft drop shadow behind the Earth, with this opacity (from 0 to 1.)
+    #[arg(long, env = "SATPAPER_SHADOW", value_parser = parse_opacity)]
+    pub shadow: Option<f64>,
+    /// How soft the drop shadow is (and how far it's offset), as a percentage of the disk's radius.
+    #[arg(long, env = "SATPAPER_SHADOW_SIZE", value_parser = parse_pixels, default_value_t = 5.0)]
+    pub shadow_size: f64,
+    /// The sector of the satellite's imagery to display.
+    /// 
+    /// Defaults to the full disk. Other sectors are rectangular and will fill the
+    /// entire wallpaper, ignoring the disk size and any background image.
+    /// 
+    /// Not every sector is available for every satellite - CONUS is GOES East only,
+    /// PACUS is GOES West only, and Japan is Himawari only.
+    #[arg(long, env = "SATPAPER_SECTOR", value_enum, default_value_t = Sector::FullDisk)]
+    pub sector: Sector,
+    /// Fill the wallpaper with a region of the full disk, given as `LAT1,LON1,LAT2,LON2`
+    /// (two opposite corners, in degrees north and east.)
+    /// 
+    /// Only the tiles covering the region are downloaded, at the highest zoom level. The region
+    /// is widened (or heightened) to match the wallpaper's aspect ratio, so all of it stays visible.
+    /// The disk layout options and any background image are ignored.
+    #[arg(long, env = "SATPAPER_REGION", value_parser = parse_region, allow_hyphen_values = true)]
+    pub region: Option<Region>,
+    /// The imagery product to display.
+    /// 
+    /// Defaults to GeoColor. The clean infrared product is a good choice if you want
+    /// to see clouds at night.
+    /// 
+    /// Not every product is available for every satellite - day cloud phase is GOES only.
+    #[arg(long, env = "SATPAPER_PRODUCT", value_enum, default_value_t = Product::Geocolor)]
+    pub product: Product,
+    /// How many zoom levels above (or below, if negative) the minimum needed to cover
+    /// the disk to download imagery at.
+    /// 
+    /// By default, Satpaper downloads the smallest zoom level whose native resolution
+    /// is at least as large as the disk. Each extra level quadruples the amount of data
+    /// downloaded, in exchange for a sharper downscale.
+    #[arg(long, env = "SATPAPER_ZOOM_BIAS", default_value_t = 0, allow_negative_numbers = true)]
+    pub zoom_bias: i32,
+    /// Which of the recently published frames to display.
+    /// 
+    /// Accepts `newest` (the default), a number `N` for the Nth newest frame, or a settling
+    /// delay like `15min` for the newest frame captured at least that long ago.
+    /// 
+    /// Holding back a frame or two gives SLIDER time to finish publishing (and fixing) it.
+    #[arg(long, env = "SATPAPER_FRAME", value_parser = parse_frame_policy, default_value = "newest", conflicts_with_all = ["at", "ago"])]
+    pub frame: FramePolicy,
+    /// Display the frame captured closest to this UTC time, instead of the latest one.
+    /// 
+    /// Expects the form `YYYY-MM-DDThh:mm[:ss]Z` - for example, `2026-09-01T18:00Z`.
+    #[arg(long, env = "SATPAPER_AT", value_parser = time::parse_utc, conflicts_with = "ago")]
+    pub at: Option<SystemTime>,
+    /// Display the frame captured closest to this long ago, instead of the latest one.
+    /// 
+    /// Accepts durations like `24h`, `90min` or `2days`. When running in the background,
+    /// the wallpaper will follow the current time with this delay.
+    #[arg(long, env = "SATPAPER_AGO", value_parser = humantime::parse_duration)]
+    pub ago: Option<Duration>,
+    /// Export a timelapse of this many of the most recent frames, then exit.
+    /// 
+    /// Each frame is composited exactly like a normal wallpaper. The animation is saved
+    /// to a file called "satpaper_timelapse" (with the format's extension) at the target path,
+    /// and your existing wallpaper is left untouched.
+    #[arg(long, env = "SATPAPER_TIMELAPSE", value_parser = clap::value_parser!(u32).range(2..))]
+    pub timelapse: Option<u32>,
+    /// The file format to encode timelapses as.
+    #[arg(long, env = "SATPAPER_TIMELAPSE_FORMAT", value_enum, default_value_t = TimelapseFormat::Webp)]
+    pub timelapse_format: TimelapseFormat,
+    /// How long each frame of a timelapse is displayed for.
+    #[arg(long, env = "SATPAPER_FRAME_DELAY", value_parser = humantime::parse_duration, default_value = "100ms")]
+    pub frame_delay: Duration,
+    /// How many times a timelapse plays before stopping. Zero means forever.
+    #[arg(long, env = "SATPAPER_LOOP_COUNT", default_value_t = 0)]
+    pub loop_count: u16,
+    /// The base URL of the SLIDER instance to scrape imagery from.
+    /// 
+    /// Useful for pointing Satpaper at a caching mirror or a local test server.
+    #[arg(long, env = "SATPAPER_SLIDER_URL", default_value = DEFAULT_SLIDER_URL)]
+    pub slider_url: String,
+    /// The path (relative to the SLIDER URL) of the list of latest frame timestamps.
+    /// 
+    /// Supports the `{satellite}`, `{sector}` and `{product}` placeholders.
+    #[arg(long, env = "SATPAPER_LATEST_TIMES_PATH", default_value = DEFAULT_LATEST_TIMES_PATH)]
+    pub latest_times_path: String,
+    /// The path (relative to the SLIDER URL) of the list of available dates.
+    /// 
+    /// Supports the `{satellite}`, `{sector}` and `{product}` placeholders.
+    #[arg(long, env = "SATPAPER_AVAILABLE_DATES_PATH", default_value = DEFAULT_AVAILABLE_DATES_PATH)]
+    pub available_dates_path: String,
+    /// The path (relative to the SLIDER URL) of the list of frames captured on a given date.
+    /// 
+    /// Supports the `{satellite}`, `{sector}`, `{product}` and `{date}` placeholders.
+    #[arg(long, env = "SATPAPER_TIMES_BY_DATE_PATH", default_value = DEFAULT_TIMES_BY_DATE_PATH)]
+    pub times_by_date_path: String,
+    /// The path (relative to the SLIDER URL) of a single imagery tile.
+    /// 
+    /// Supports the `{satellite}`, `{sector}`, `{product}`, `{year}`, `{month}`, `{day}`,
+    /// `{timestamp}`, `{zoom}`, `{row}` and `{column}` placeholders. (SLIDER itself names
+    /// tiles `{row}_{column}`, counting from the top left.)
+    #[arg(long, env = "SATPAPER_TILE_PATH", default_value = DEFAULT_TILE_PATH)]
+    pub tile_path: String,
+    /// Extra HTTP headers to send with every request, in `Name: value` form.
+    /// 
+    /// Can be specified multiple times, or one per line in the environment variable.
+    /// (Header values can't contain newlines, but often contain commas.)
+    /// Useful for mirrors that require an authentication token.
+    #[arg(long = "header", env = "SATPAPER_HEADERS", value_parser = parse_header, value_delimiter = '\n')]
+    pub headers: Vec<Header>,
+    /// A proxy to send every request through, e.g. `http://proxy.example.com:8080`.
+    /// 
+    /// Defaults to the `HTTPS_PROXY`/`HTTP_PROXY` (depending on the SLIDER URL) or `ALL_PROXY`
+    /// environment variables. Hosts listed in `NO_PROXY` bypass the proxy either way.
+    #[arg(long, env = "SATPAPER_PROXY")]
+    pub proxy: Option<String>,
+    /// A PEM file of extra root certificates to trust, on top of the built-in ones.
+    /// 
+    /// Useful behind TLS-intercepting corporate proxies.
+    #[arg(long, env = "SATPAPER_CA_FILE")]
+    pub ca_file: Option<PathBuf>,
+    /// The most imagery to download per (UTC) day, in megabytes.
+    /// 
+    /// Tiles loaded from the cache don't count. What happens once the budget is used up
+    /// depends on --over-budget.
+    #[arg(long, env = "SATPAPER_DAILY_BUDGET")]
+    pub daily_budget: Option<u64>,
+    /// The most imagery to download per (UTC) calendar month, in megabytes.
+    #[arg(long, env = "SATPAPER_MONTHLY_BUDGET")]
+    pub monthly_budget: Option<u64>,
+    /// What to do once a daily or monthly budget is used up.
+    #[arg(long, env = "SATPAPER_OVER_BUDGET", value_enum, default_value_t = OverBudget::Downgrade)]
+    pub over_budget: OverBudget,
+    /// The fastest imagery may be downloaded, in kilobytes per second.
+    #[arg(long, env = "SATPAPER_MAX_BANDWIDTH", value_parser = clap::value_parser!(u64).range(1..))]
+    pub max_bandwidth: Option<u64>,
+    /// The most tile requests that may be in flight at once.
+    #[arg(long, env = "SATPAPER_MAX_CONNECTIONS", value_parser = clap::value_parser!(u32).range(1..), default_value_t = 4)]
+    pub max_connections: u32,
+    /// The most requests that may be sent to SLIDER per second, across all connections.
+    /// 
+    /// Regardless of this limit, Satpaper backs off for as long as SLIDER asks it to
+    /// when it responds with "429 Too Many Requests" or "503 Service Unavailable."
+    #[arg(long, env = "SATPAPER_REQUESTS_PER_SECOND", value_parser = parse_rate, default_value_t = 10.0)]
+    pub requests_per_second: f64,
+    /// Where to keep track of how much data has been downloaded each day, as a JSON
+    /// object mapping `YYYYMMDD` dates to bytes.
+    /// 
+    /// Defaults to "satpaper/usage.json" inside your platform's data directory
+    /// (e.g. `~/.local/share` on Linux.)
+    #[arg(long, env = "SATPAPER_USAGE_FILE")]
+    pub usage_file: Option<PathBuf>,
+    /// Print how much data has been downloaded today and this month (against any budgets), then exit.
+    #[arg(long, default_value_t = false)]
+    pub usage: bool,
+    /// Where to cache downloaded imagery tiles.
+    /// 
+    /// Defaults to a "satpaper" directory inside your platform's cache directory
+    /// (e.g. `~/.cache/satpaper` on Linux.) Cached tiles make re-renders and restarts
+    /// free of charge, bandwidth-wise.
+    #[arg(long, env = "SATPAPER_CACHE_DIR")]
+    pub cache_dir: Option<PathBuf>,
+    /// The maximum size of the tile cache, in megabytes. Zero disables caching entirely.
+    /// 
+    /// Once the limit is exceeded, the oldest tiles are evicted first.
+    #[arg(long, env = "SATPAPER_CACHE_SIZE", default_value_t = 512)]
+    pub cache_size: u64,
+    /// How many times to retry a tile that failed to download (timeouts, resets, server errors.)
+    #[arg(long, env = "SATPAPER_RETRIES", default_value_t = 4)]
+    pub retries: u32,
+    /// How long to wait before the first retry of a failed tile.
+    /// 
+    /// The delay doubles (with some random jitter) for every subsequent retry.
+    #[arg(long, env = "SATPAPER_RETRY_DELAY", value_parser = humantime::parse_duration, default_value = "1s")]
+    pub retry_delay: Duration,
+    /// The longest downloading a single frame is allowed to take, retries included.
+    /// 
+    /// Tiles that SLIDER hasn't finished publishing yet are retried until this deadline passes.
+    #[arg(long, env = "SATPAPER_FRAME_DEADLINE", value_parser = humantime::parse_duration, default_value = "5min")]
+    pub frame_deadline: Duration,
+    /// The percentage of a frame's tiles that may fail (or come back blank) before the
+    /// whole frame is rejected.
+    /// 
+    /// Failed tiles are filled in from the last successfully downloaded frame, or left black
+    /// if there isn't one. Set to zero to reject any frame with a missing tile.
+    #[arg(long, env = "SATPAPER_MAX_SUBSTITUTED", value_parser = clap::value_parser!(u32).range(0..=100), default_value_t = 10)]
+    pub max_substituted: u32,
+    /// The percentage of the disk (or sector) that may be missing data before a frame
+    /// is considered glitched.
+    ///
+    /// Glitched frames - missing data, black bands or suspiciously small tiles - are
+    /// rejected in favor of an older one.
+    #[arg(long, env = "SATPAPER_MAX_MISSING", value_parser = clap::value_parser!(u32).range(0..=100), default_value_t = 5)]
+    pub max_missing: u32,
+    /// How many older frames to fall back on when the chosen one (the newest, or the one
+    /// picked with --at or --ago) is glitched.
+    #[arg(long, env = "SATPAPER_FALLBACK_FRAMES", default_value_t = 3)]
+    pub fallback_frames: usize,
+    /// Whether or not to skip checking frames for glitches entirely.
+    ///
+    /// Products that are only available during the day (like Natural Color) are black on
+    /// the night side, which would otherwise be mistaken for missing data.
+    #[arg(long, env = "SATPAPER_SKIP_QUALITY_CHECK", default_value_t = false)]
+    pub skip_quality_check: bool,
+    /// How old the selected frame can be before Satpaper warns that SLIDER may have
+    /// stopped publishing new imagery.
+    #[arg(long, env = "SATPAPER_STALE_AFTER", value_parser = humantime::parse_duration, default_value = "1h")]
+    pub stale_after: Duration,
 }
 
+pub const DEFAULT_SLIDER_URL: &str = "https://rammb-slider.cira.colostate.edu";
+pub const DEFAULT_LATEST_TIMES_PATH: &str = "data/json/{satellite}/{sector}/{product}/latest_times.json";
+pub const DEFAULT_AVAILABLE_DATES_PATH: &str = "data/json/{satellite}/{sector}/{product}/available_dates.json";
+pub const DEFAULT_TIMES_BY_DATE_PATH: &str = "data/json/{satellite}/{sector}/{product}/{date}_by_hour.json";
+pub const DEFAULT_TILE_PATH: &str = "data/imagery/{year}/{month}/{day}/{satellite}---{sector}/{product}/{timestamp}/{zoom}/{row}_{column}.png";
+
 #[derive(Debug, Copy, Clone, ValueEnum)]
 pub enum Satellite {
     GOESEast,
@@ -71,13 +322,363 @@ pub enum Satellite {
     Meteosat10,
 }
 
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ValueEnum)]
+pub enum Sector {
+    /// The entire visible disk of the Earth.
+    FullDisk,
+    /// The continental United States (GOES East only.)
+    Conus,
+    /// The Pacific and western United States (GOES West only.)
+    Pacus,
+    /// The first movable mesoscale sector (GOES) or target area (Himawari.)
+    #[value(name = "mesoscale-1")]
+    Mesoscale1,
+    /// The second movable mesoscale sector (GOES only.)
+    #[value(name = "mesoscale-2")]
+    Mesoscale2,
+    /// Japan and the surrounding region (Himawari only.)
+    Japan,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ValueEnum)]
+pub enum Product {
+    /// CIRA GeoColor - true color by day, infrared with city lights by night.
+    Geocolor,
+    /// Clean longwave infrared window (ABI/AHI band 13, SEVIRI band 9.)
+    CleanIr,
+    /// Air mass RGB.
+    Airmass,
+    /// Dust RGB.
+    Dust,
+    /// Natural color RGB (day land cloud on GOES and Himawari.)
+    NaturalColor,
+    /// Day cloud phase distinction RGB (GOES only.)
+    DayCloudPhase,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ValueEnum)]
+pub enum TimelapseFormat {
+    /// Animated WebP (lossless.)
+    Webp,
+    /// Animated PNG.
+    Apng,
+    /// GIF (limited to 256 colors per frame.)
+    Gif,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ValueEnum)]
+pub enum DiskScale {
+    /// A percentage of the wallpaper's smaller dimension - the whole disk is always visible.
+    Smaller,
+    /// A percentage of the wallpaper's larger dimension.
+    Larger,
+    /// A percentage of the wallpaper's diagonal.
+    Diagonal,
+    /// As wide as the wallpaper, cropped at the top and bottom (the disk size is ignored.)
+    Cover,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ValueEnum)]
+pub enum Align {
+    TopLeft,
+    Top,
+    TopRight,
+    Left,
+    Center,
+    Right,
+    BottomLeft,
+    Bottom,
+    BottomRight,
+}
+
+impl Align {
+    /// How many halves of the wallpaper's free space lie before the disk, along each axis.
+    fn halves(self) -> (i64, i64) {
+        use Align::*;
+
+        let x = match self {
+            TopLeft | Left | BottomLeft => 0,
+            Top | Center | Bottom => 1,
+            TopRight | Right | BottomRight => 2,
+        };
+
+        let y = match self {
+            TopLeft | Top | TopRight => 0,
+            Left | Center | Right => 1,
+            BottomLeft | Bottom | BottomRight => 2,
+        };
+
+        (x, y)
+    }
+}
+
+/// A distance along one axis of the wallpaper.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Length {
+    Pixels(i64),
+    /// A percentage of the wallpaper's size along the same axis.
+    Percent(f64),
+}
+
+impl Length {
+    fn resolve(self, total: u32) -> i64 {
+        match self {
+            Self::Pixels(pixels) => pixels,
+            Self::Percent(percent) => (total as f64 * percent / 100.0).round() as i64,
+        }
+    }
+}
+
+/// How far to move the disk from its aligned position.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Offset {
+    pub x: Length,
+    pub y: Length,
+}
+
+/// A latitude/longitude rectangle, in degrees.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Region {
+    pub south: f64,
+    pub west: f64,
+    pub north: f64,
+    /// May be less than `west`, if the region crosses the antimeridian.
+    pub east: f64,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ValueEnum)]
+pub enum DiskDetection {
+    /// Fit a circle to the disk's edge, as seen along many rows and columns of the image.
+    Fit,
+    /// Compute the disk from the satellite's known position and imaging geometry.
+    Geometry,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ValueEnum)]
+pub enum OverBudget {
+    /// Keep updating, but at the lowest zoom level (a single tile per frame.)
+    Downgrade,
+    /// Stop downloading new imagery until the budget resets.
+    Skip,
+}
+
+/// An extra HTTP header, as passed on the command line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Header {
+    pub name: String,
+    pub value: String,
+}
+
+fn parse_header(s: &str) -> Result<Header, String> {
+    let (name, value) = s
+        .split_once(':')
+        .ok_or_else(|| format!("header `{s}` should be in `Name: value` form"))?;
+
+    let name = name.trim();
+
+    if name.is_empty() {
+        return Err(format!("header `{s}` has an empty name"));
+    }
+
+    Ok(Header {
+        name: name.to_owned(),
+        value: value.trim().to_owned(),
+    })
+}
+
+/// Which of the recently published frames to display.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FramePolicy {
+    /// The most recent frame.
+    Newest,
+    /// The Nth most recent frame, counting the newest as the first.
+    Nth(usize),
+    /// The most recent frame captured at least this long ago.
+    Settled(Duration),
+}
+
+fn parse_pixels(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(pixels) if pixels.is_finite() && pixels >= 0.0 => Ok(pixels),
+        _ => Err(format!("{s:?} is not a valid number of pixels")),
+    }
+}
+
+fn parse_opacity(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(opacity) if (0.0..=1.0).contains(&opacity) => Ok(opacity),
+        _ => Err(format!("{s:?} is not a number from 0 to 1")),
+    }
+}
+
+/// Parses a `#rrggbb` (or `rrggbb`) hex color.
+fn parse_color(s: &str) -> Result<[u8; 3], String> {
+    let hex = s.strip_prefix('#').unwrap_or(s);
+
+    if hex.len() != 6 || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(format!("{s:?} is not a color like #4d8fff"));
+    }
+
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).unwrap();
+
+    Ok([channel(0), channel(2), channel(4)])
+}
+
+fn parse_offset(s: &str) -> Result<Offset, String> {
+    let length = |s: &str| {
+        let s = s.trim();
+
+        match s.strip_suffix('%') {
+            Some(percent) => percent.parse().ok().filter(|p: &f64| p.is_finite()).map(Length::Percent),
+            None => s.parse().ok().map(Length::Pixels),
+        }
+    };
+
+    s.split_once(',')
+        .and_then(|(x, y)| Some(Offset { x: length(x)?, y: length(y)? }))
+        .ok_or_else(|| format!("{s:?} is not an offset like -200,0 or -10%,5%"))
+}
+
+fn parse_region(s: &str) -> Result<Region, String> {
+    let coords: Vec<f64> = s
+        .split(',')
+        .map(|c| c.trim().parse().ok().filter(|c: &f64| c.is_finite()))
+        .collect::<Option<_>>()
+        .ok_or_else(|| format!("{s:?} is not a region like 18,-98,31,-80"))?;
+
+    let &[lat1, lon1, lat2, lon2] = coords.as_slice() else {
+        return Err(format!("{s:?} is not a region like 18,-98,31,-80"));
+    };
+
+    if [lat1, lat2].iter().any(|lat| lat.abs() > 90.0) || [lon1, lon2].iter().any(|lon| lon.abs() > 180.0) {
+        return Err(format!("{s:?} has a latitude beyond ±90° or a longitude beyond ±180°"));
+    }
+
+    if lat1 == lat2 || lon1 == lon2 {
+        return Err(format!("{s:?} is empty"));
+    }
+
+    // No satellite can see more than half the globe, so the shorter way around is the one meant.
+    let (west, east) = if (lon1 - lon2).abs() <= 180.0 {
+        (lon1.min(lon2), lon1.max(lon2))
+    } else {
+        (lon1.max(lon2), lon1.min(lon2))
+    };
+
+    Ok(Region {
+        south: lat1.min(lat2),
+        west,
+        north: lat1.max(lat2),
+        east,
+    })
+}
+
+fn parse_rate(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(rate) if rate.is_finite() && rate > 0.0 => Ok(rate),
+        _ => Err(format!("{s:?} is not a positive number")),
+    }
+}
+
+fn parse_frame_policy(s: &str) -> Result<FramePolicy, String> {
+    let s = s.trim();
+
+    if s.eq_ignore_ascii_case("newest") {
+        return Ok(FramePolicy::Newest);
+    }
+
+    if let Ok(n) = s.parse::<usize>() {
+        return match n {
+            0 => Err("frames are counted from 1 (the newest)".into()),
+            n => Ok(FramePolicy::Nth(n)),
+        };
+    }
+
+    humantime::parse_duration(s)
+        .map(FramePolicy::Settled)
+        .map_err(|_| format!("`{s}` is not `newest`, a frame number or a duration"))
+}
+
 impl Config {
+    /// Checks for option combinations that cannot be expressed through `clap` alone.
+    pub fn validate(&self) -> Result<()> {
+        if !self.satellite.supports(self.sector) {
+            bail!(
+                "Sector {:?} is not available for satellite {:?}",
+                self.sector,
+                self.satellite
+            );
+        }
+
+        if let Some(region) = self.region {
+            if !self.sector.is_full_disk() {
+                bail!("A region can only be cut out of the full disk, not sector {:?}", self.sector);
+            }
+
+            if self.satellite.projection().bounds(region).is_none() {
+                bail!("No part of region {region:?} is visible from satellite {:?}", self.satellite);
+            }
+        }
+
+        if self.product.id(self.satellite).is_none() {
+            bail!(
+                "Product {:?} is not available for satellite {:?}",
+                self.product,
+                self.satellite
+            );
+        }
+
+        Ok(())
+    }
+
+    /// The time to display imagery from, if not the latest available.
+    pub fn target_time(&self) -> Option<SystemTime> {
+        self.at.or_else(|| {
+            self.ago.map(|ago| SystemTime::now() - ago)
+        })
+    }
+
+    /// The part of the full-resolution full disk to show, if a region was requested,
+    /// widened or heightened to match the wallpaper's aspect ratio.
+    pub fn region_bounds(&self) -> Option<Bounds> {
+        let projection = self.satellite.projection();
+        let aspect = self.resolution_x as f64 / self.resolution_y as f64;
+
+        projection
+            .bounds(self.region?)
+            .map(|bounds| bounds.with_aspect(aspect, projection.size))
+    }
+
     pub fn disk(&self) -> u32 {
-        let smaller_dim = self.resolution_x.min(self.resolution_y);
+        let (width, height) = (self.resolution_x as f64, self.resolution_y as f64);
+
+        let reference = match self.disk_scale {
+            DiskScale::Smaller => width.min(height),
+            DiskScale::Larger => width.max(height),
+            DiskScale::Diagonal => width.hypot(height),
+            DiskScale::Cover => return self.resolution_x,
+        };
 
-        let disk_dim = smaller_dim as f32 * (self.disk_size as f32 / 100.0);
+        let disk_dim = reference * (self.disk_size as f64 / 100.0);
         disk_dim.floor() as u32
     }
+
+    /// Where the disk's top-left corner goes on the wallpaper.
+    /// 
+    /// Depending on the layout, this may be off the edge of the wallpaper - only the part
+    /// of the disk that's on it is drawn.
+    pub fn disk_position(&self) -> (i64, i64) {
+        let disk = i64::from(self.disk());
+        let (align_x, align_y) = self.align.halves();
+
+        let x = (i64::from(self.resolution_x) - disk) * align_x / 2;
+        let y = (i64::from(self.resolution_y) - disk) * align_y / 2;
+
+        match self.offset {
+            Some(offset) => (x + offset.x.resolve(self.resolution_x), y + offset.y.resolve(self.resolution_y)),
+            None => (x, y),
+        }
+    }
 }
 
 impl Satellite {
@@ -93,35 +694,213 @@ impl Satellite {
         }
     }
 
-    pub fn max_zoom(self) -> u32 {
+    /// Whether or not SLIDER publishes the given sector for this satellite.
+    pub fn supports(self, sector: Sector) -> bool {
         use Satellite::*;
+        use Sector::*;
 
-        match self {
-            GOESEast | GOESWest | Himawari => 4,
-            Meteosat9 | Meteosat10 => 3,
+        match sector {
+            FullDisk => true,
+            Conus => matches!(self, GOESEast),
+            Pacus => matches!(self, GOESWest),
+            Mesoscale1 => matches!(self, GOESEast | GOESWest | Himawari),
+            Mesoscale2 => matches!(self, GOESEast | GOESWest),
+            Japan => matches!(self, Himawari),
+        }
+    }
+
+    pub fn max_zoom(self, sector: Sector) -> u32 {
+        use Satellite::*;
+        use Sector::*;
+
+        match (self, sector) {
+            (GOESEast | GOESWest | Himawari, FullDisk) => 4,
+            (Meteosat9 | Meteosat10, FullDisk) => 3,
+            (_, Conus | Pacus) => 4,
+            (_, Mesoscale1 | Mesoscale2 | Japan) => 2,
         }
     }
 
-    pub fn tile_image(self) -> Image<Box<[u8]>, 3> {
-        Image::alloc(self.tile_size(), self.tile_size()).boxed()
+    /// How the satellite's full-disk imagery maps onto the Earth.
+    pub fn projection(self) -> Projection {
+        use Satellite::*;
+
+        let (tile_width, _) = self.tile_size(Sector::FullDisk);
+        let size = tile_width * self.tile_count(Sector::FullDisk);
+
+        // Pixel steps are those of each imager's 1km (ABI, AHI) or 3km (SEVIRI) full disk.
+        let (longitude, step, sweep) = match self {
+            GOESEast => (-75.2, 28e-6, Sweep::X),
+            GOESWest => (-137.2, 28e-6, Sweep::X),
+            Himawari => (140.7, 1.0_f64.to_radians() / 624.5933, Sweep::Y),
+            Meteosat9 => (45.5, 1.0_f64.to_radians() / 208.1652, Sweep::Y),
+            Meteosat10 => (0.0, 1.0_f64.to_radians() / 208.1652, Sweep::Y),
+        };
+
+        Projection { longitude, step, size, sweep }
+    }
+
+    /// The number of tiles along each edge of the (always square) tile grid.
+    pub fn tile_count(self, sector: Sector) -> u32 {
+        1 << self.max_zoom(sector)
     }
 
-    pub fn tile_count(self) -> u32 {
+    /// The width and height of a single tile at maximum zoom.
+    pub fn tile_size(self, sector: Sector) -> (u32, u32) {
         use Satellite::*;
+        use Sector::*;
+
+        match (self, sector) {
+            (GOESEast | GOESWest, FullDisk) => (678, 678),
+            (Himawari, FullDisk) => (688, 688),
+            (Meteosat9 | Meteosat10, FullDisk) => (464, 464),
+            (_, Conus | Pacus) => (625, 375),
+            (_, Mesoscale1 | Mesoscale2) => (500, 500),
+            (_, Japan) => (600, 600),
+        }
+    }
+}
+
+impl Sector {
+    pub fn id(self) -> &'static str {
+        use Sector::*;
 
         match self {
-            GOESEast | GOESWest | Himawari => 16,
-            Meteosat9 | Meteosat10 => 8,
+            FullDisk => "full_disk",
+            // SLIDER files GOES West's PACUS sector under the same ID as GOES East's CONUS.
+            Conus | Pacus => "conus",
+            Mesoscale1 => "mesoscale_01",
+            Mesoscale2 => "mesoscale_02",
+            Japan => "japan",
         }
     }
 
-    pub fn tile_size(self) -> u32 {
-        use Satellite::*;
+    /// Whether or not the sector covers the entire visible disk of the Earth.
+    pub fn is_full_disk(self) -> bool {
+        matches!(self, Sector::FullDisk)
+    }
+}
+
+impl TimelapseFormat {
+    pub fn extension(self) -> &'static str {
+        use TimelapseFormat::*;
 
         match self {
-            GOESEast | GOESWest => 678,
-            Himawari => 688,
-            Meteosat9 | Meteosat10 => 464,
+            Webp => "webp",
+            Apng => "png",
+            Gif => "gif",
         }
     }
-}
\ No newline at end of file
+}
+
+impl Product {
+    /// The SLIDER ID of this product for the given satellite, if the satellite has it.
+    /// 
+    /// GOES and Himawari share most names, but Meteosat's SEVIRI imager numbers
+    /// its bands differently and names its RGBs after the EUMETSAT recipes.
+    pub fn id(self, satellite: Satellite) -> Option<&'static str> {
+        use Product::*;
+        use Satellite::*;
+
+        let id = match (self, satellite) {
+            (Geocolor, _) => "geocolor",
+            (CleanIr, GOESEast | GOESWest | Himawari) => "band_13",
+            (CleanIr, Meteosat9 | Meteosat10) => "band_09",
+            (Airmass, _) => "airmass",
+            (Dust, _) => "dust",
+            (NaturalColor, GOESEast | GOESWest | Himawari) => "day_land_cloud",
+            (NaturalColor, Meteosat9 | Meteosat10) => "natural_color",
+            (DayCloudPhase, GOESEast | GOESWest) => "day_cloud_phase_distinction",
+            (DayCloudPhase, _) => return None,
+        };
+
+        Some(id)
+    }
+}
+
+#[test]
+fn test_parse_header() {
+    assert_eq!(
+        parse_header("Authorization: Bearer abc:123"),
+        Ok(Header { name: "Authorization".into(), value: "Bearer abc:123".into() })
+    );
+    assert!(parse_header("no-colon").is_err());
+    assert!(parse_header(": value").is_err());
+
+    let base = ["satpaper", "-s", "goes-east", "-x", "1920", "-y", "1080", "-d", "90", "-t", "."];
+    let headers = |args: &[&str]| Config::try_parse_from(base.iter().chain(args)).unwrap().headers;
+
+    // Commas are part of the value, not separators.
+    assert_eq!(
+        headers(&["--header", "Accept: text/html, application/json", "--header", "X-Token: abc"]),
+        [
+            Header { name: "Accept".into(), value: "text/html, application/json".into() },
+            Header { name: "X-Token".into(), value: "abc".into() },
+        ]
+    );
+}
+
+#[test]
+fn test_parse_frame_policy() {
+    assert_eq!(parse_frame_policy("newest"), Ok(FramePolicy::Newest));
+    assert_eq!(parse_frame_policy("3"), Ok(FramePolicy::Nth(3)));
+    assert_eq!(parse_frame_policy("15min"), Ok(FramePolicy::Settled(Duration::from_secs(900))));
+    assert!(parse_frame_policy("0").is_err());
+    assert!(parse_frame_policy("latest").is_err());
+}
+
+#[test]
+fn test_product_ids() {
+    assert_eq!(Product::CleanIr.id(Satellite::GOESEast), Some("band_13"));
+    assert_eq!(Product::CleanIr.id(Satellite::Meteosat10), Some("band_09"));
+    assert_eq!(Product::DayCloudPhase.id(Satellite::Himawari), None);
+}
+
+#[test]
+fn test_parse_color() {
+    assert_eq!(parse_color("#4d8fff"), Ok([0x4d, 0x8f, 0xff]));
+    assert_eq!(parse_color("FFA500"), Ok([255, 165, 0]));
+    assert!(parse_color("#4d8ff").is_err());
+    assert!(parse_color("#4d8fgg").is_err());
+    assert!(parse_color("#é4d8ff").is_err());
+}
+
+#[test]
+fn test_parse_region() {
+    let gulf = Region { south: 18.0, west: -98.0, north: 31.0, east: -80.0 };
+    assert_eq!(parse_region("18,-98,31,-80"), Ok(gulf));
+    assert_eq!(parse_region("31, -80, 18, -98"), Ok(gulf));
+
+    // Across the antimeridian, the short way round.
+    assert_eq!(parse_region("-50,165,-30,-175"), Ok(Region { south: -50.0, west: 165.0, north: -30.0, east: -175.0 }));
+
+    assert!(parse_region("18,-98,31").is_err());
+    assert!(parse_region("18,-98,95,-80").is_err());
+    assert!(parse_region("18,-98,18,-80").is_err());
+}
+
+#[test]
+fn test_parse_offset() {
+    assert_eq!(parse_offset("-200,0"), Ok(Offset { x: Length::Pixels(-200), y: Length::Pixels(0) }));
+    assert_eq!(parse_offset("-10%, 5.5%"), Ok(Offset { x: Length::Percent(-10.0), y: Length::Percent(5.5) }));
+    assert!(parse_offset("10").is_err());
+    assert!(parse_offset("10px,0").is_err());
+}
+
+#[test]
+fn test_disk_layout() {
+    let layout = |args: &[&str]| {
+        let base = ["satpaper", "-s", "goes-east", "-x", "3440", "-y", "1440", "-d", "90", "-t", "."];
+        let config = Config::try_parse_from(base.iter().chain(args)).unwrap();
+
+        (config.disk(), config.disk_position())
+    };
+
+    assert_eq!(layout(&[]), (1296, (1072, 72)));
+    assert_eq!(layout(&["--align", "left"]), (1296, (0, 72)));
+    assert_eq!(layout(&["--align", "bottom-right", "--offset", "-100,10%"]), (1296, (2044, 288)));
+    assert_eq!(layout(&["--disk-scale", "larger"]), (3096, (172, -828)));
+    assert_eq!(layout(&["--disk-scale", "diagonal", "--align", "top"]), (3356, (42, 0)));
+    assert_eq!(layout(&["--disk-scale", "cover", "--align", "top"]), (3440, (0, 0)));
+    assert_eq!(layout(&["--disk-scale", "cover"]), (3440, (0, -1000)));
+}
@@ -0,0 +1,209 @@
+use crate::composite::Image;
+
+/// How many evenly spaced rows (and columns) are scanned for the disk's edge.
+const SCANLINES: u32 = 64;
+/// How many candidate circles (through three random edge points) are tried.
+const HYPOTHESES: usize = 256;
+/// How many times the best candidate is refined with a least-squares fit to the points that agree with it.
+const REFITS: usize = 2;
+/// The fewest edge points a circle will be fit to.
+const MIN_POINTS: usize = 8;
+/// How far (in pixels, or as a fraction of the radius, whichever is larger) an edge point
+/// may be from a circle and still agree with it.
+const TOLERANCE: (f64, f64) = (1.5, 0.004);
+
+/// A circle, in pixel coordinates (with pixel centers at whole numbers.)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Circle {
+    pub x: f64,
+    pub y: f64,
+    pub radius: f64,
+}
+
+/// Finds the Earth's disk in a full-disk image by fitting a circle to its edge,
+/// as seen along many rows and columns.
+///
+/// Pixels count as Earth if any of their channels is brighter than `threshold`. The fit is
+/// RANSAC-style, so edge points that disagree with the rest (like a limb on the night side,
+/// or noise in space) are ignored as long as they're in the minority.
+pub fn fit(image: Image<&[u8]>, threshold: u8) -> Option<Circle> {
+    let points = edge_points(image, threshold);
+
+    if points.len() < MIN_POINTS {
+        return None;
+    }
+
+    let inliers = |circle: Circle| -> Vec<(f64, f64)> {
+        let tolerance = TOLERANCE.0.max(circle.radius * TOLERANCE.1);
+
+        points
+            .iter()
+            .copied()
+            .filter(|&(x, y)| ((x - circle.x).hypot(y - circle.y) - circle.radius).abs() <= tolerance)
+            .collect()
+    };
+
+    // A fixed seed keeps the fit (and so the wallpaper) deterministic.
+    let mut state = 0x9e37_79b9_7f4a_7c15_u64;
+    let mut random = |n: usize| {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        (state % n as u64) as usize
+    };
+
+    let best = (0..HYPOTHESES)
+        .filter_map(|_| {
+            let [a, b, c] = [(); 3].map(|_| points[random(points.len())]);
+            circumcircle(a, b, c)
+        })
+        .max_by_key(|&circle| inliers(circle).len())?;
+
+    let mut circle = best;
+
+    for _ in 0..REFITS {
+        circle = fit_points(&inliers(circle))?;
+    }
+
+    Some(circle)
+}
+
+/// The circle passing through three points, if they aren't (nearly) collinear.
+fn circumcircle(a: (f64, f64), b: (f64, f64), c: (f64, f64)) -> Option<Circle> {
+    let (bx, by) = (b.0 - a.0, b.1 - a.1);
+    let (cx, cy) = (c.0 - a.0, c.1 - a.1);
+
+    let d = 2.0 * (bx * cy - by * cx);
+
+    if d.abs() < 1e-6 {
+        return None;
+    }
+
+    let (b2, c2) = (bx * bx + by * by, cx * cx + cy * cy);
+    let x = (cy * b2 - by * c2) / d;
+    let y = (bx * c2 - cx * b2) / d;
+
+    Some(Circle {
+        x: a.0 + x,
+        y: a.1 + y,
+        radius: x.hypot(y),
+    })
+}
+
+/// The first and last Earth pixel along each scanline, skipping any that touch the image's
+/// edge (where the disk is cut off, rather than ending.)
+fn edge_points(image: Image<&[u8]>, threshold: u8) -> Vec<(f64, f64)> {
+    let (width, height) = (image.width(), image.height());
+    let is_earth = |x: u32, y: u32| unsafe { image.pixel(x, y) }.into_iter().any(|c| c > threshold);
+
+    let mut points = Vec::new();
+
+    for i in 1..=SCANLINES {
+        let y = i * height / (SCANLINES + 1);
+        let first = (0..width).find(|&x| is_earth(x, y));
+        let last = (0..width).rfind(|&x| is_earth(x, y));
+
+        if let (Some(first), Some(last)) = (first, last) {
+            // The edge lies between the last pixel of space and the first pixel of Earth.
+            if first > 0 {
+                points.push((first as f64 - 0.5, y as f64));
+            }
+
+            if last < width - 1 {
+                points.push((last as f64 + 0.5, y as f64));
+            }
+        }
+
+        let x = i * width / (SCANLINES + 1);
+        let first = (0..height).find(|&y| is_earth(x, y));
+        let last = (0..height).rfind(|&y| is_earth(x, y));
+
+        if let (Some(first), Some(last)) = (first, last) {
+            if first > 0 {
+                points.push((x as f64, first as f64 - 0.5));
+            }
+
+            if last < height - 1 {
+                points.push((x as f64, last as f64 + 0.5));
+            }
+        }
+    }
+
+    points
+}
+
+/// A least-squares (Kåsa) circle fit, solving `x² + y² + Dx + Ey + F = 0` for D, E and F.
+fn fit_points(points: &[(f64, f64)]) -> Option<Circle> {
+    if points.len() < MIN_POINTS {
+        return None;
+    }
+
+    // Working relative to the centroid keeps the sums small and the solution stable.
+    let n = points.len() as f64;
+    let mean_x = points.iter().map(|p| p.0).sum::<f64>() / n;
+    let mean_y = points.iter().map(|p| p.1).sum::<f64>() / n;
+
+    let (mut xx, mut xy, mut yy, mut xz, mut yz, mut z) = (0.0, 0.0, 0.0, 0.0, 0.0, 0.0);
+
+    for &(x, y) in points {
+        let (x, y) = (x - mean_x, y - mean_y);
+        let r = x * x + y * y;
+
+        xx += x * x;
+        xy += x * y;
+        yy += y * y;
+        xz += x * r;
+        yz += y * r;
+        z += r;
+    }
+
+    // The centered x and y sums are zero, which decouples F from D and E.
+    let det = xx * yy - xy * xy;
+
+    if det.abs() < f64::EPSILON {
+        return None;
+    }
+
+    let d = -(xz * yy - yz * xy) / det;
+    let e = -(yz * xx - xz * xy) / det;
+    let f = -z / n;
+
+    let radius = (d * d / 4.0 + e * e / 4.0 - f).sqrt();
+
+    radius.is_finite().then_some(Circle {
+        x: mean_x - d / 2.0,
+        y: mean_y - e / 2.0,
+        radius,
+    })
+}
+
+#[test]
+fn test_fit() {
+    // An off-center disk with a dark limb off to one side and a speck of noise in space.
+    let (cx, cy, r) = (52.3, 47.8, 40.0);
+
+    let buf: Box<[u8]> = (0..100)
+        .flat_map(|y| (0..100).map(move |x| (x as f64, y as f64)))
+        .flat_map(|(x, y)| {
+            let dist = (x - cx).hypot(y - cy);
+
+            if (x, y) == (5.0, 50.0) {
+                [255; 3]
+            } else if dist < r - 6.0 || (dist < r && x < cx + 20.0) {
+                [90, 120, 200]
+            } else {
+                [0; 3]
+            }
+        })
+        .collect();
+
+    let image = Image::build(100, 100).buf(buf);
+    let circle = fit(image.as_ref(), 4).unwrap();
+
+    assert!((circle.x - cx).abs() < 1.0, "{circle:?}");
+    assert!((circle.y - cy).abs() < 1.0, "{circle:?}");
+    assert!((circle.radius - r).abs() < 1.0, "{circle:?}");
+
+    let black = Image::alloc(100, 100).boxed();
+    assert!(fit(black.as_ref(), 4).is_none());
+}
@@ -0,0 +1,101 @@
+use rayon::prelude::*;
+
+use crate::composite::Image;
+use crate::disk::Circle;
+
+/// Calls `f` with every row of `image` (and its index) that lies within `reach` of the circle, in parallel.
+fn rows_near(mut image: Image<&mut [u8]>, circle: Circle, reach: f64, f: impl Fn(usize, &mut [u8]) + Sync) {
+    let stride = image.width() as usize * 3;
+    let top = (circle.y - circle.radius - reach).floor().max(0.0) as usize;
+    let bottom = (circle.y + circle.radius + reach).ceil().max(0.0) as usize;
+
+    // SAFETY: we only write whole pixels, within the image's bounds.
+    unsafe { image.buffer_mut() }
+        .par_chunks_exact_mut(stride)
+        .enumerate()
+        .skip(top)
+        .take(bottom.saturating_sub(top) + 1)
+        .for_each(|(y, row)| f(y, row));
+}
+
+/// Darkens `bg` with a soft shadow of the disk, cast down and to the right.
+///
+/// `size` (in pixels) is how far the shadow is blurred; it's offset by half that.
+pub fn shadow(bg: Image<&mut [u8]>, disk: Circle, size: f64, opacity: f64) {
+    let size = size.max(1.0);
+    let shadow = Circle {
+        x: disk.x + size / 2.0,
+        y: disk.y + size / 2.0,
+        radius: disk.radius,
+    };
+
+    rows_near(bg, shadow, size, |y, row| {
+        let dy = y as f64 - shadow.y;
+
+        for (x, px) in row.chunks_exact_mut(3).enumerate() {
+            let dist = (x as f64 - shadow.x).hypot(dy);
+
+            // Fully dark a blur's width inside the edge, fading out to nothing a blur's width outside it.
+            let t = ((shadow.radius + size - dist) / (2.0 * size)).clamp(0.0, 1.0);
+            let alpha = opacity * smoothstep(t);
+
+            if alpha > 0.0 {
+                px.iter_mut().for_each(|c| *c = (f64::from(*c) * (1.0 - alpha)).round() as u8);
+            }
+        }
+    });
+}
+
+/// Lights up `bg` with a halo of `color` around the disk, fading out over `size` pixels beyond its edge.
+///
+/// The halo is brightest (exactly `color`) at the edge, so darker colors give fainter halos.
+pub fn glow(bg: Image<&mut [u8]>, disk: Circle, color: [u8; 3], size: f64) {
+    let size = size.max(1.0);
+
+    rows_near(bg, disk, size, |y, row| {
+        let dy = y as f64 - disk.y;
+
+        for (x, px) in row.chunks_exact_mut(3).enumerate() {
+            // The glow starts a pixel inside the edge, so it blends into the feathered disk.
+            let t = ((x as f64 - disk.x).hypot(dy) - disk.radius + 1.0) / (size + 1.0);
+
+            if (0.0..1.0).contains(&t) {
+                let intensity = (1.0 - t).powi(2);
+
+                // Screen blending - light adds up without ever clipping.
+                for (c, glow) in px.iter_mut().zip(color) {
+                    let glow = f64::from(glow) * intensity;
+                    *c = (255.0 - (255.0 - f64::from(*c)) * (255.0 - glow) / 255.0).round() as u8;
+                }
+            }
+        }
+    });
+}
+
+fn smoothstep(t: f64) -> f64 {
+    t * t * (3.0 - 2.0 * t)
+}
+
+#[test]
+fn test_effects() {
+    let disk = Circle { x: 50.0, y: 50.0, radius: 20.0 };
+    let pixel = |image: &Image<Box<[u8]>>, x: u32, y: u32| unsafe { image.pixel(x, y) };
+
+    let mut bg = Image::build(100, 100).buf(vec![100; 100 * 100 * 3].into_boxed_slice());
+    shadow(bg.as_mut(), disk, 10.0, 0.5);
+
+    // Darkest under the (offset) disk, untouched far away and lighter down-right than up-left.
+    assert_eq!(pixel(&bg, 55, 55), [50; 3]);
+    assert_eq!(pixel(&bg, 5, 5), [100; 3]);
+    assert!(pixel(&bg, 68, 68)[0] < pixel(&bg, 32, 32)[0]);
+
+    let mut bg = Image::alloc(100, 100).boxed();
+    glow(bg.as_mut(), disk, [0, 128, 255], 10.0);
+
+    // Brightest at the edge, fading out with distance, and only in the glow's color.
+    let [r, g, b] = pixel(&bg, 71, 50);
+    assert_eq!(r, 0);
+    assert!(b > g && b > pixel(&bg, 76, 50)[2]);
+    assert_eq!(pixel(&bg, 82, 50), [0; 3]);
+    assert_eq!(pixel(&bg, 50, 50), [0; 3]);
+}
@@ -1,7 +1,20 @@
 #![feature(once_cell_try, isqrt)]
 
+mod cache;
+mod composite;
 mod config;
+mod disk;
+mod effects;
+mod net;
+mod projection;
+mod quality;
+mod retry;
 mod slider;
+mod source;
+mod tile;
+mod time;
+mod timelapse;
+mod usage;
 mod wallpaper;
 
 use std::time::Duration;
@@ -11,6 +24,8 @@ use anyhow::{Result, Context};
 use clap::Parser;
 
 use crate::config::*;
+use crate::slider::Slider;
+use crate::source::ImagerySource;
 
 const OUTPUT_NAME: &str = "satpaper_latest.png";
 const SLEEP_DURATION: Duration = Duration::from_secs(60);
@@ -30,18 +45,36 @@ fn main() -> Result<()> {
 
 fn update_wallpaper() -> Result<()> {
     let config = Config::parse();
+    config.validate()?;
+
+    if config.usage {
+        return usage::report(&config);
+    }
+
+    let source = Slider::new(&config)?;
+
+    if let Some(count) = config.timelapse {
+        return timelapse::export(&config, &source, count);
+    }
     
     let mut timestamp = None;
     
     loop  {
         log::debug!("Checking timestamp...");
 
-        let new = slider::fetch_latest_timestamp(&config)
-            .unwrap_or_else(|err| {
+        // Resolved exactly once per cycle - everything below works with this frame.
+        let new = match config
+            .target_time()
+            .map_or_else(|| source.select_frame(config.frame), |time| source.frame_near(time))
+        {
+            Ok(new) => new,
+            Err(err) => {
                 log::error!("Failed to fetch latest timestamp: {err}");
                 log::error!("Check aborted; waiting until next go round.");
-                timestamp.unwrap_or(0)
-            });
+                sleep(SLEEP_DURATION);
+                continue;
+            }
+        };
 
         if timestamp
             .map_or(true, |old| old != new)
@@ -50,7 +83,7 @@ fn update_wallpaper() -> Result<()> {
             log::debug!("Old timestamp: {timestamp:?}, new timestamp: {new}");
             log::info!("Fetching updated source and compositing new wallpaper...");
 
-            if slider::composite_latest_image(&config)? {
+            if composite::composite_latest_image(&config, &source, new)? {
                 timestamp = Some(new);
 
                 if config.once {
@@ -83,16 +116,66 @@ mod tests {
     fn generate_wallpaper() -> Result<()> {
         let config = Config {
             satellite: Satellite::GOESEast,
+            sector: Sector::FullDisk,
+            region: None,
+            product: Product::Geocolor,
+            zoom_bias: 0,
+            frame: FramePolicy::Newest,
+            at: None,
+            ago: None,
+            timelapse: None,
+            timelapse_format: TimelapseFormat::Webp,
+            frame_delay: Duration::from_millis(100),
+            loop_count: 0,
             resolution_x: 2556,
             resolution_y: 1440,
             disk_size: 95,
+            disk_scale: DiskScale::Smaller,
+            align: Align::Center,
+            offset: None,
             target_path: ".".into(),
             wallpaper_command: None,
             once: false,
-            background_image: None
+            background_image: None,
+            disk_detection: DiskDetection::Fit,
+            edge_threshold: 4,
+            feather: 1.0,
+            glow: None,
+            glow_size: 6.0,
+            shadow: None,
+            shadow_size: 5.0,
+            slider_url: DEFAULT_SLIDER_URL.into(),
+            latest_times_path: DEFAULT_LATEST_TIMES_PATH.into(),
+            available_dates_path: DEFAULT_AVAILABLE_DATES_PATH.into(),
+            times_by_date_path: DEFAULT_TIMES_BY_DATE_PATH.into(),
+            tile_path: DEFAULT_TILE_PATH.into(),
+            headers: Vec::new(),
+            proxy: None,
+            ca_file: None,
+            daily_budget: None,
+            monthly_budget: None,
+            over_budget: OverBudget::Downgrade,
+            max_bandwidth: None,
+            max_connections: 4,
+            requests_per_second: 10.0,
+            usage_file: None,
+            usage: false,
+            cache_dir: None,
+            cache_size: 0,
+            retries: 4,
+            retry_delay: Duration::from_secs(1),
+            frame_deadline: Duration::from_secs(300),
+            max_substituted: 10,
+            max_missing: 5,
+            fallback_frames: 3,
+            skip_quality_check: false,
+            stale_after: Duration::from_secs(3600),
         };
 
-        slider::composite_latest_image(&config)?;
+        let source = Slider::new(&config)?;
+        let frame = source.select_frame(config.frame)?;
+
+        composite::composite_latest_image(&config, &source, frame)?;
 
         std::fs::remove_file("./satpaper_latest.png")?;
 
@@ -0,0 +1,213 @@
+use crate::config::Region;
+use crate::disk::Circle;
+
+/// The Earth's equatorial radius, in kilometers.
+const EQUATORIAL_RADIUS: f64 = 6378.137;
+/// The Earth's polar radius, in kilometers.
+const POLAR_RADIUS: f64 = 6356.7523;
+/// The distance from the Earth's center to a geostationary satellite, in kilometers.
+const ORBIT_RADIUS: f64 = 42164.16;
+
+/// How a geostationary satellite's full-disk imagery maps onto the Earth.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Projection {
+    /// The longitude the satellite sits above, in degrees east.
+    pub longitude: f64,
+    /// The scan angle covered by each pixel of the full-resolution full disk, in radians.
+    pub step: f64,
+    /// The width (and height) of the full-resolution full disk, in pixels.
+    pub size: u32,
+    /// Which axis the imager's mirror sweeps along.
+    pub sweep: Sweep,
+}
+
+/// The axis a geostationary imager scans along, which changes how scan angles are measured.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sweep {
+    /// GOES' ABI scans east-west.
+    X,
+    /// Himawari's AHI and Meteosat's SEVIRI scan north-south.
+    Y,
+}
+
+/// A rectangle of the full-resolution full disk, in pixels.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Bounds {
+    pub left: f64,
+    pub top: f64,
+    pub right: f64,
+    pub bottom: f64,
+}
+
+impl Projection {
+    /// Where the Earth's disk lies in a `width` by `height` rendition of the full disk.
+    ///
+    /// The sub-satellite point is assumed to be at the center of the image.
+    pub fn disk(&self, width: u32, height: u32) -> Circle {
+        let half_angle = (EQUATORIAL_RADIUS / ORBIT_RADIUS).asin();
+
+        Circle {
+            x: (width - 1) as f64 / 2.0,
+            y: (height - 1) as f64 / 2.0,
+            radius: half_angle / self.step * width as f64 / self.size as f64,
+        }
+    }
+
+    /// Where the given point on the Earth lies in the full-resolution full disk,
+    /// or `None` if it's out of the satellite's sight.
+    ///
+    /// This is the normalized geostationary projection from the CGMS LRIT/HRIT specification
+    /// (and the GOES-R product user guide.)
+    pub fn pixel(&self, latitude: f64, longitude: f64) -> Option<(f64, f64)> {
+        let (a, b, h) = (EQUATORIAL_RADIUS, POLAR_RADIUS, ORBIT_RADIUS);
+
+        let latitude = ((b * b) / (a * a) * latitude.to_radians().tan()).atan();
+        let longitude = (longitude - self.longitude).to_radians();
+
+        let e2 = 1.0 - (b * b) / (a * a);
+        let r = b / (1.0 - e2 * latitude.cos().powi(2)).sqrt();
+
+        let sx = h - r * latitude.cos() * longitude.cos();
+        let sy = -r * latitude.cos() * longitude.sin();
+        let sz = r * latitude.sin();
+
+        // Points on the far side of the limb are hidden by the Earth itself.
+        if h * (h - sx) < sy * sy + (a * a) / (b * b) * sz * sz {
+            return None;
+        }
+
+        let norm = (sx * sx + sy * sy + sz * sz).sqrt();
+
+        let (x, y) = match self.sweep {
+            Sweep::X => ((-sy / norm).asin(), (sz / sx).atan()),
+            Sweep::Y => ((-sy / sx).atan(), (sz / norm).asin()),
+        };
+
+        let center = (self.size - 1) as f64 / 2.0;
+
+        Some((center + x / self.step, center - y / self.step))
+    }
+
+    /// The smallest rectangle of the full disk that contains every visible part of `region`,
+    /// or `None` if none of it is visible.
+    pub fn bounds(&self, region: Region) -> Option<Bounds> {
+        // Lines of latitude and longitude are curved in the projection, so the region is sampled
+        // throughout rather than just at its corners.
+        const SAMPLES: u32 = 64;
+
+        let span = (region.east - region.west).rem_euclid(360.0);
+        let lerp = |from: f64, to: f64, i: u32| from + (to - from) * f64::from(i) / f64::from(SAMPLES);
+
+        (0..=SAMPLES)
+            .flat_map(|i| (0..=SAMPLES).map(move |j| (i, j)))
+            .filter_map(|(i, j)| {
+                self.pixel(lerp(region.south, region.north, i), lerp(region.west, region.west + span, j))
+            })
+            .fold(None, |bounds: Option<Bounds>, (x, y)| {
+                Some(match bounds {
+                    None => Bounds { left: x, top: y, right: x, bottom: y },
+                    Some(bounds) => Bounds {
+                        left: bounds.left.min(x),
+                        top: bounds.top.min(y),
+                        right: bounds.right.max(x),
+                        bottom: bounds.bottom.max(y),
+                    },
+                })
+            })
+            .map(|bounds| Bounds {
+                left: bounds.left.floor().max(0.0),
+                top: bounds.top.floor().max(0.0),
+                right: (bounds.right.ceil() + 1.0).min(self.size as f64),
+                bottom: (bounds.bottom.ceil() + 1.0).min(self.size as f64),
+            })
+    }
+}
+
+impl Bounds {
+    pub fn width(&self) -> f64 {
+        self.right - self.left
+    }
+
+    pub fn height(&self) -> f64 {
+        self.bottom - self.top
+    }
+
+    /// Grows the bounds about their center until they have the given aspect ratio (width / height),
+    /// shifting them back inside a `size` by `size` full disk if they spill over its edges.
+    ///
+    /// If the full disk isn't big enough for that, the bounds are cropped to fit it instead.
+    pub fn with_aspect(self, aspect: f64, size: u32) -> Self {
+        let size = size as f64;
+
+        let (width, height) = if self.width() / self.height() < aspect {
+            (self.height() * aspect, self.height())
+        } else {
+            (self.width(), self.width() / aspect)
+        };
+
+        let (width, height) = if width > size {
+            (size, size / aspect)
+        } else if height > size {
+            (size * aspect, size)
+        } else {
+            (width, height)
+        };
+
+        let place = |start: f64, end: f64, len: f64| {
+            let start = ((start + end) / 2.0 - len / 2.0).clamp(0.0, size - len);
+            (start, start + len)
+        };
+
+        let (left, right) = place(self.left, self.right, width);
+        let (top, bottom) = place(self.top, self.bottom, height);
+
+        Self { left, top, right, bottom }
+    }
+}
+
+#[test]
+fn test_disk() {
+    // GOES' 1km full disk is 10848 pixels of 28µrad, which the Earth's equator just about fills.
+    let projection = Projection { longitude: -75.2, step: 28e-6, size: 10848, sweep: Sweep::X };
+    let disk = projection.disk(1000, 1000);
+
+    assert_eq!((disk.x, disk.y), (499.5, 499.5));
+    assert!((499.0..500.0).contains(&disk.radius), "{disk:?}");
+}
+
+#[test]
+fn test_pixel() {
+    let projection = Projection { longitude: -75.2, step: 28e-6, size: 10848, sweep: Sweep::X };
+    let center = (10848 - 1) as f64 / 2.0;
+
+    // The sub-satellite point is dead center, north is up and east is right.
+    assert_eq!(projection.pixel(0.0, -75.2), Some((center, center)));
+
+    let (x, y) = projection.pixel(25.0, -90.0).unwrap();
+    assert!(x < center && y < center, "({x}, {y})");
+
+    // The worked example from the GOES-R product user guide (for a satellite at 75W.)
+    let pug = Projection { longitude: -75.0, ..projection };
+    let (x, y) = pug.pixel(33.846162, -84.690932).unwrap();
+    assert!(((x - center) * 28e-6 + 0.024052).abs() < 1e-6, "{x}");
+    assert!(((center - y) * 28e-6 - 0.095340).abs() < 1e-6, "{y}");
+
+    // The far side of the Earth isn't visible.
+    assert_eq!(projection.pixel(0.0, 104.8), None);
+    assert_eq!(projection.pixel(0.0, -160.0), None);
+
+    let gulf = Region { south: 18.0, west: -98.0, north: 31.0, east: -80.0 };
+    let bounds = projection.bounds(gulf).unwrap();
+    assert!(bounds.left < bounds.right && bounds.top < bounds.bottom, "{bounds:?}");
+    assert!(bounds.right < center && bounds.bottom < center, "{bounds:?}");
+
+    let wide = bounds.with_aspect(2.0, 10848);
+    assert!((wide.width() / wide.height() - 2.0).abs() < 1e-9, "{wide:?}");
+    assert_eq!(wide.height(), bounds.height());
+
+    let everything = Bounds { left: 0.0, top: 0.0, right: 10848.0, bottom: 10848.0 }.with_aspect(2.0, 10848);
+    assert_eq!(everything.width(), 10848.0);
+    assert_eq!(everything.top, (10848.0 - 5424.0) / 2.0);
+
+    assert!(projection.bounds(Region { south: -10.0, west: 100.0, north: 10.0, east: 110.0 }).is_none());
+}
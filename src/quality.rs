@@ -0,0 +1,237 @@
+use std::fmt;
+
+use crate::composite::Image;
+use crate::disk::Circle;
+use crate::source::Shape;
+
+/// Pixels no brighter than this (in every channel) are treated as missing data.
+const NO_DATA: u8 = 8;
+/// How much of the disk's (measured) radius is inspected - the limb is often legitimately dark.
+const DISK_MARGIN: f64 = 0.95;
+/// How far from the center (as a fraction of the radius) rows and columns are checked for black lines.
+///
+/// Any further out and polar night can black out an entire chord on its own.
+const LINE_EXTENT: f64 = 0.8;
+/// Lines shorter than this are too short to meaningfully call black.
+const MIN_LINE: u32 = 16;
+/// A tile that compresses to less than the median size divided by this is considered abnormal.
+const MIN_TILE_RATIO: usize = 8;
+
+/// A frame that downloaded successfully, but whose contents look broken.
+#[derive(Debug)]
+pub struct Glitch(String);
+
+impl fmt::Display for Glitch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Frame failed quality check: {}", self.0)
+    }
+}
+
+impl std::error::Error for Glitch {}
+
+/// The encoded size of a downloaded tile, by its position in the stitched image.
+#[derive(Debug, Clone, Copy)]
+pub struct TileSize {
+    pub column: u32,
+    pub row: u32,
+    pub bytes: usize,
+}
+
+/// Checks a stitched frame for the telltale signs of a SLIDER glitch: missing data,
+/// black rows or columns and tiles that are far smaller than their neighbors.
+pub fn inspect(
+    image: Image<&[u8]>,
+    shape: Shape,
+    tile_size: (u32, u32),
+    tiles: &[TileSize],
+    max_missing: u32,
+) -> Result<(), Glitch> {
+    let center = ((image.width() - 1) as f64 / 2.0, (image.height() - 1) as f64 / 2.0);
+
+    let (radius, area) = match shape {
+        Shape::Disk => (disk_radius(image)? * DISK_MARGIN, "disk"),
+        Shape::Rectangle => (f64::INFINITY, "sector"),
+    };
+
+    inspect_within(image, center, radius, area, tile_size, tiles, max_missing)
+}
+
+/// Like [`inspect`], but for a region cut out of the full disk, where anything beyond
+/// the Earth's `limb` (in the region's pixel coordinates) is space.
+pub fn inspect_region(
+    image: Image<&[u8]>,
+    limb: Circle,
+    tile_size: (u32, u32),
+    tiles: &[TileSize],
+    max_missing: u32,
+) -> Result<(), Glitch> {
+    inspect_within(image, (limb.x, limb.y), limb.radius * DISK_MARGIN, "region", tile_size, tiles, max_missing)
+}
+
+/// Inspects the pixels within `radius` of `center`, which `area` names in any error.
+fn inspect_within(
+    image: Image<&[u8]>,
+    (center_x, center_y): (f64, f64),
+    radius: f64,
+    area: &str,
+    tile_size: (u32, u32),
+    tiles: &[TileSize],
+    max_missing: u32,
+) -> Result<(), Glitch> {
+    let (width, height) = (image.width(), image.height());
+
+    let inside = |x: f64, y: f64| (x - center_x).hypot(y - center_y) <= radius;
+
+    // (inspected, missing) pixel counts, per row and per column.
+    let mut rows = vec![(0, 0); height as usize];
+    let mut columns = vec![(0, 0); width as usize];
+
+    for (i, px) in image.bytes().chunks_exact(3).enumerate() {
+        let (x, y) = (i % width as usize, i / width as usize);
+
+        if !inside(x as f64, y as f64) {
+            continue;
+        }
+
+        let missing = u32::from(is_missing(px));
+
+        rows[y].0 += 1;
+        rows[y].1 += missing;
+        columns[x].0 += 1;
+        columns[x].1 += missing;
+    }
+
+    let (inspected, missing) = rows
+        .iter()
+        .fold((0u64, 0u64), |(a, b), &(c, d)| (a + u64::from(c), b + u64::from(d)));
+
+    if missing * 100 > inspected * u64::from(max_missing) {
+        return Err(Glitch(format!(
+            "{:.1}% of the {area} is missing data, more than the {max_missing}% allowed",
+            missing as f64 * 100.0 / inspected as f64
+        )));
+    }
+
+    let black_lines = |lines: &[(u32, u32)], center: f64| {
+        lines
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| (*i as f64 - center).abs() <= radius * LINE_EXTENT)
+            .filter(|(_, &(inspected, missing))| inspected >= MIN_LINE && missing * 50 >= inspected * 49)
+            .map(|(i, _)| i)
+            .collect::<Vec<_>>()
+    };
+
+    for (lines, center, name) in [(&rows, center_y, "row"), (&columns, center_x, "column")] {
+        let black = black_lines(lines, center);
+
+        if let Some(first) = black.first() {
+            return Err(Glitch(format!("{} black {name}(s), starting at {name} {first}", black.len())));
+        }
+    }
+
+    // Only tiles that lie entirely within the inspected area are comparable.
+    let (tile_width, tile_height) = tile_size;
+    let mut sizes: Vec<_> = tiles
+        .iter()
+        .filter(|tile| {
+            let (left, top) = ((tile.column * tile_width) as f64, (tile.row * tile_height) as f64);
+            let (right, bottom) = (left + (tile_width - 1) as f64, top + (tile_height - 1) as f64);
+
+            [(left, top), (right, top), (left, bottom), (right, bottom)]
+                .into_iter()
+                .all(|(x, y)| inside(x, y))
+        })
+        .collect();
+
+    if sizes.len() >= 4 {
+        sizes.sort_unstable_by_key(|tile| tile.bytes);
+        let median = sizes[sizes.len() / 2].bytes;
+
+        if let Some(tile) = sizes.iter().find(|tile| tile.bytes * MIN_TILE_RATIO < median) {
+            return Err(Glitch(format!(
+                "tile at row {}, column {} is only {} bytes (median {median})",
+                tile.row, tile.column, tile.bytes
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+fn is_missing(px: &[u8]) -> bool {
+    px.iter().all(|&c| c <= NO_DATA)
+}
+
+/// Measures the radius of the disk along the middle row and column, taking the larger of
+/// the two so that a single glitched line can't shrink it.
+fn disk_radius(image: Image<&[u8]>) -> Result<f64, Glitch> {
+    let (width, height) = (image.width(), image.height());
+
+    let span = |pixels: Vec<[u8; 3]>| {
+        let first = pixels.iter().position(|px| !is_missing(px));
+        let last = pixels.iter().rposition(|px| !is_missing(px));
+
+        first.zip(last).map_or(0, |(first, last)| last - first + 1)
+    };
+
+    // SAFETY: every coordinate is within the image's bounds.
+    let row = span((0..width).map(|x| unsafe { image.pixel(x, height / 2) }).collect());
+    let column = span((0..height).map(|y| unsafe { image.pixel(width / 2, y) }).collect());
+
+    match row.max(column) {
+        0 => Err(Glitch("the frame is entirely black".into())),
+        diameter => Ok(diameter as f64 / 2.0),
+    }
+}
+
+#[test]
+fn test_inspect() {
+    let frame = |f: &dyn Fn(u32, u32) -> [u8; 3]| {
+        let buf: Box<[u8]> = (0..64)
+            .flat_map(|y| (0..64).map(move |x| (x, y)))
+            .flat_map(|(x, y)| f(x, y))
+            .collect();
+
+        Image::build(64, 64).buf(buf)
+    };
+
+    let tiles = |sizes: [usize; 4]| {
+        let mut sizes = sizes.into_iter();
+
+        (0..2)
+            .flat_map(|row| (0..2).map(move |column| (column, row)))
+            .map(|(column, row)| TileSize { column, row, bytes: sizes.next().unwrap() })
+            .collect::<Vec<_>>()
+    };
+
+    let check = |image: &Image<Box<[u8]>>, shape, sizes| {
+        inspect(image.as_ref(), shape, (32, 32), &tiles(sizes), 5)
+    };
+
+    let clean = frame(&|_, _| [60, 80, 120]);
+    assert!(check(&clean, Shape::Disk, [1000; 4]).is_ok());
+    // Disks don't have any tiles entirely inside them at this zoom level.
+    assert!(check(&clean, Shape::Disk, [1000, 1000, 1000, 10]).is_ok());
+    assert!(check(&clean, Shape::Rectangle, [1000, 1000, 1000, 10]).is_err());
+
+    // Black outside the disk is expected...
+    let corners = frame(&|x, y| if x.min(y) < 4 { [0; 3] } else { [60, 80, 120] });
+    assert!(check(&corners, Shape::Disk, [1000; 4]).is_ok());
+
+    // ...but not inside it.
+    let band = frame(&|_, y| if y == 30 { [0; 3] } else { [60, 80, 120] });
+    assert!(check(&band, Shape::Disk, [1000; 4]).unwrap_err().0.contains("black row"));
+
+    let column = frame(&|x, _| if x == 40 { [0; 3] } else { [60, 80, 120] });
+    assert!(check(&column, Shape::Disk, [1000; 4]).unwrap_err().0.contains("black column"));
+
+    let half = frame(&|x, y| if x > 32 && y > 32 { [0; 3] } else { [60, 80, 120] });
+    assert!(check(&half, Shape::Disk, [1000; 4]).unwrap_err().0.contains("missing data"));
+
+    // Regions may run off the edge of the Earth, where space is expected to be black.
+    let limb = Circle { x: 0.0, y: 0.0, radius: 40.0 };
+    let region = frame(&|x, y| if x.pow(2) + y.pow(2) > 38 * 38 { [0; 3] } else { [60, 80, 120] });
+    assert!(inspect_region(region.as_ref(), limb, (32, 32), &tiles([1000; 4]), 5).is_ok());
+    assert!(check(&region, Shape::Rectangle, [1000; 4]).is_err());
+}
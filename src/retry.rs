@@ -0,0 +1,170 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Result};
+
+use crate::net;
+
+/// The longest we'll ever wait between two attempts.
+const MAX_DELAY: Duration = Duration::from_secs(60);
+
+/// How failed requests are retried.
+#[derive(Debug, Clone, Copy)]
+pub struct Backoff {
+    /// How many times a request that failed with a transient error is retried.
+    pub retries: u32,
+    /// The delay before the first retry, which doubles with each subsequent one.
+    pub base: Duration,
+}
+
+/// What a failed attempt means for the next one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Failure {
+    /// The resource doesn't exist (yet) - SLIDER lists frames before all of their tiles are published.
+    NotPublished,
+    /// A timeout, reset connection or server-side error that may well succeed next time.
+    Transient,
+    /// Anything else - retrying would just fail the same way.
+    Permanent,
+}
+
+impl Backoff {
+    /// The delay before the given (zero-based) retry: exponential, with the upper half jittered
+    /// so a fleet of clients that failed together don't all retry together.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let full = self.base
+            .saturating_mul(1 << attempt.min(16))
+            .min(MAX_DELAY);
+
+        let half = full / 2;
+        let jitter = RandomState::new().build_hasher().finish() as f64 / u64::MAX as f64;
+
+        half + half.mul_f64(jitter)
+    }
+
+    /// Runs `op` until it succeeds, a permanent error occurs, retries are exhausted or `deadline` passes.
+    ///
+    /// `op` is passed the time remaining until the deadline, which should be used as its timeout.
+    /// Missing (404) resources are retried until the deadline regardless of the retry count,
+    /// since they are expected to appear once publishing finishes.
+    pub fn run<T>(&self, what: &str, deadline: Instant, mut op: impl FnMut(Duration) -> Result<T>) -> Result<T> {
+        let mut attempt = 0;
+
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+
+            if remaining.is_zero() {
+                bail!("Deadline exceeded while fetching {what}");
+            }
+
+            let err = match op(remaining) {
+                Ok(value) => return Ok(value),
+                Err(err) => err,
+            };
+
+            let delay = match classify(&err) {
+                Failure::Permanent => return Err(err),
+                Failure::Transient if attempt >= self.retries => {
+                    return Err(err.context(format!("Giving up on {what} after {attempt} retries")));
+                }
+                Failure::Transient => {
+                    log::warn!("Failed to fetch {what} ({err}), retrying...");
+
+                    // Servers shedding load (429/503) may tell us exactly how long to back off for.
+                    let delay = self.delay(attempt);
+                    retry_after(&err).map_or(delay, |after| after.max(delay))
+                }
+                Failure::NotPublished => {
+                    log::info!("Waiting for {what} to be published...");
+                    self.delay(attempt.min(self.retries))
+                }
+            };
+
+            if Instant::now() + delay >= deadline {
+                return Err(err.context(format!("Deadline exceeded while fetching {what}")));
+            }
+
+            sleep(delay);
+            attempt += 1;
+        }
+    }
+}
+
+fn retry_after(err: &anyhow::Error) -> Option<Duration> {
+    match err.downcast_ref::<ureq::Error>()? {
+        ureq::Error::Status(429 | 503, resp) => net::retry_after(resp),
+        _ => None,
+    }
+}
+
+fn classify(err: &anyhow::Error) -> Failure {
+    if let Some(err) = err.downcast_ref::<ureq::Error>() {
+        return match err {
+            ureq::Error::Status(404, _) => Failure::NotPublished,
+            ureq::Error::Status(408 | 429 | 500..=599, _) => Failure::Transient,
+            ureq::Error::Status(..) => Failure::Permanent,
+            ureq::Error::Transport(_) => Failure::Transient,
+        };
+    }
+
+    if err.downcast_ref::<std::io::Error>().is_some() {
+        return Failure::Transient;
+    }
+
+    Failure::Permanent
+}
+
+#[test]
+fn test_backoff_delay() {
+    let backoff = Backoff {
+        retries: 3,
+        base: Duration::from_secs(1),
+    };
+
+    for attempt in 0..4 {
+        let full = Duration::from_secs(1 << attempt);
+        let delay = backoff.delay(attempt);
+
+        assert!(delay >= full / 2 && delay <= full, "{delay:?} for attempt {attempt}");
+    }
+
+    assert!(backoff.delay(30) <= MAX_DELAY);
+}
+
+#[test]
+fn test_backoff_run() {
+    let backoff = Backoff {
+        retries: 2,
+        base: Duration::from_millis(1),
+    };
+
+    let deadline = Instant::now() + Duration::from_secs(10);
+
+    // Transient errors are retried until the retry count runs out...
+    let mut calls = 0;
+    let result: Result<()> = backoff.run("test", deadline, |_| {
+        calls += 1;
+        Err(std::io::Error::other("reset").into())
+    });
+    assert!(result.is_err());
+    assert_eq!(calls, 3);
+
+    // ...permanent ones aren't retried at all...
+    let mut calls = 0;
+    let result: Result<()> = backoff.run("test", deadline, |_| {
+        calls += 1;
+        bail!("bad data")
+    });
+    assert!(result.is_err());
+    assert_eq!(calls, 1);
+
+    // ...and success short-circuits.
+    let mut calls = 0;
+    let result = backoff.run("test", deadline, |_| {
+        calls += 1;
+        if calls < 2 { Err(std::io::Error::other("reset").into()) } else { Ok(calls) }
+    });
+    assert_eq!(result.unwrap(), 2);
+}
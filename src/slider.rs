@@ -1,347 +1,931 @@
-use std::sync::{PoisonError, OnceLock, Mutex};
-use std::time::Duration;
+use std::collections::BTreeMap;
+use std::ops::Range;
+use std::sync::{Arc, PoisonError, Mutex};
+use std::time::{Duration, Instant, SystemTime};
 
-use anyhow::{Result, Context};
-use fimg::{OverlayAt, Image as Img, scale::Lanczos3};
+use anyhow::{bail, Context, Result};
+use fimg::scale::Lanczos3;
 use rayon::prelude::*;
-use serde::{Deserialize, de};
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use serde::Deserialize;
+
+use ureq::{Agent, Request};
+
+use crate::cache::{self, TileCache, TileKey};
+use crate::composite::{blit, crop, Canvas, Image, Timings};
+use crate::config::{Config, FramePolicy, Header, OverBudget, Satellite, Sector};
+use crate::disk::Circle;
+use crate::net::{self, TIMEOUT};
+use crate::projection::Bounds;
+use crate::quality::{self, TileSize};
+use crate::retry::Backoff;
+use crate::source::{Geometry, ImagerySource, Shape};
+use crate::tile;
+use crate::time::{self, FrameTime};
+use crate::usage::{self, Budget, Ledger, Throttle};
+
+/// A stitched frame, at its native size.
+type Mosaic = Arc<Image<Box<[u8]>>>;
+
+/// Imagery scraped from RAMMB SLIDER (or a mirror with the same layout.)
+pub struct Slider {
+    satellite: Satellite,
+    sector: Sector,
+    /// The part of the full-resolution full disk to download, if not all of it.
+    region: Option<Bounds>,
+    product: &'static str,
+    zoom_bias: i32,
+    agent: Agent,
+    base_url: String,
+    latest_times_path: String,
+    available_dates_path: String,
+    times_by_date_path: String,
+    tile_path: String,
+    headers: Vec<Header>,
+    cache: Option<TileCache>,
+    backoff: Backoff,
+    frame_deadline: Duration,
+    max_substituted: u32,
+    max_missing: u32,
+    quality_check: bool,
+    stale_after: Duration,
+    /// The last frame that downloaded successfully, shared so that failed frames can't lose it.
+    previous: Mutex<Option<Mosaic>>,
+    latest_times: Mutex<Option<Revalidate<Times>>>,
+    ledger: Ledger,
+    budget: Budget,
+    over_budget: OverBudget,
+    throttle: Option<Throttle>,
+    /// Tiles are downloaded on their own pool, so that `--max-connections` bounds how many are in flight.
+    pool: ThreadPool,
+}
 
-use ureq::AgentBuilder;
+impl Slider {
+    pub fn new(config: &Config) -> Result<Self> {
+        let agent = net::agent(config)?;
+
+        let pool = ThreadPoolBuilder::new()
+            .num_threads(config.max_connections as usize)
+            .thread_name(|i| format!("satpaper-download-{i}"))
+            .build()
+            .context("Failed to start the download thread pool")?;
+
+        let cache = match (config.cache_size, config.cache_dir.clone().or_else(cache::default_dir)) {
+            (0, _) => None,
+            (size, Some(dir)) => Some(TileCache::new(dir, size * 1024 * 1024)),
+            (_, None) => {
+                log::warn!("Could not determine a cache directory; tile caching is disabled.");
+                None
+            }
+        };
 
-use super::{
-    Config,
-    OUTPUT_NAME
-};
+        Ok(Self {
+            satellite: config.satellite,
+            sector: config.sector,
+            region: config.region_bounds(),
+            product: config.product
+                .id(config.satellite)
+                .with_context(|| format!(
+                    "Product {:?} is not available for satellite {:?}",
+                    config.product,
+                    config.satellite
+                ))?,
+            zoom_bias: config.zoom_bias,
+            agent,
+            base_url: config.slider_url.trim_end_matches('/').to_owned(),
+            latest_times_path: config.latest_times_path.clone(),
+            available_dates_path: config.available_dates_path.clone(),
+            times_by_date_path: config.times_by_date_path.clone(),
+            tile_path: config.tile_path.clone(),
+            headers: config.headers.clone(),
+            cache,
+            backoff: Backoff {
+                retries: config.retries,
+                base: config.retry_delay,
+            },
+            frame_deadline: config.frame_deadline,
+            max_substituted: config.max_substituted,
+            max_missing: config.max_missing,
+            quality_check: !config.skip_quality_check,
+            stale_after: config.stale_after,
+            previous: Mutex::new(None),
+            latest_times: Mutex::new(None),
+            ledger: Ledger::load(config.usage_file.clone().or_else(usage::default_path))?,
+            budget: Budget::from_config(config),
+            over_budget: config.over_budget,
+            throttle: config.max_bandwidth.map(|kb| Throttle::new(kb * 1024)),
+            pool,
+        })
+    }
 
-/// rgb all the way down
-pub type Image<T> = Img<T, 3>;
+    /// Builds a GET request for the given path template, with any extra headers attached.
+    fn get(&self, template: &str, vars: &[(&str, String)]) -> Request {
+        let vars = [
+            ("satellite", self.satellite.id().to_owned()),
+            ("sector", self.sector.id().to_owned()),
+            ("product", self.product.to_owned()),
+        ]
+        .into_iter()
+        .chain(vars.iter().cloned());
+
+        let path = expand(template, vars);
+        let url = format!("{}/{}", self.base_url, path.trim_start_matches('/'));
+
+        self.headers
+            .iter()
+            .fold(self.agent.get(&url), |req, header| {
+                req.set(&header.name, &header.value)
+            })
+    }
+}
 
-const SLIDER_BASE_URL: &str = "https://rammb-slider.cira.colostate.edu";
-const SLIDER_SECTOR: &str = "full_disk";
-const SLIDER_PRODUCT: &str = "geocolor";
+/// Whether or not every pixel in the image is pure black.
+fn is_blank(image: Image<&[u8]>) -> bool {
+    image.bytes().iter().all(|&b| b == 0)
+}
 
-const TIMEOUT: Duration = Duration::from_secs(30);
+/// Picks the smallest zoom level whose native resolution is at least `target`,
+/// then shifts it by `bias` levels (clamped to the available range.)
+/// 
+/// Each zoom level doubles the number of tiles along each edge, starting from a single tile at zero.
+fn pick_zoom(tile_size: (u32, u32), max_zoom: u32, bias: i32, target: (u32, u32)) -> u32 {
+    let (tile_width, tile_height) = tile_size;
+    let (width, height) = target;
 
-pub fn composite_latest_image(config: &Config) -> Result<bool> {
-    download(config)
-        .and_then(|image| { composite(config, image)?; Ok(true) })
-        .or_else(|err| {
-            log::error!("Failed to download source image: {err}");
-            log::error!("Composition aborted; waiting until next go round.");
-            Ok(false)
-        })
+    let zoom = (0..=max_zoom)
+        .find(|zoom| tile_width << zoom >= width && tile_height << zoom >= height)
+        .unwrap_or(max_zoom);
+
+    zoom.saturating_add_signed(bias).min(max_zoom)
 }
 
-fn download(config: &Config) -> Result<Image<Box<[u8]>>> {
-    let tile_count = config.satellite.tile_count();
+/// Picks a frame out of a list of timestamps (newest first) according to `policy`.
+fn select(policy: FramePolicy, timestamps: &[u64], now: SystemTime) -> Option<u64> {
+    match policy {
+        FramePolicy::Newest => timestamps.first().copied(),
+        FramePolicy::Nth(n) => timestamps.get(n - 1).copied(),
+        FramePolicy::Settled(delay) => timestamps
+            .iter()
+            .copied()
+            .find(|&timestamp| FrameTime::parse(timestamp).is_ok_and(|time| time.age(now) >= delay)),
+    }
+}
 
-    let agent = AgentBuilder::new()
-        .timeout(TIMEOUT)
-        .user_agent("satpaper")
-        .build();
+/// The (up to) `count` distinct timestamps from before `frame`, newest first.
+fn older(frame: u64, timestamps: impl IntoIterator<Item = u64>, count: usize) -> Vec<u64> {
+    let mut older: Vec<_> = timestamps
+        .into_iter()
+        .filter(|&timestamp| timestamp < frame)
+        .collect();
 
-    let time = Time::fetch(config)?;
-    let (year, month, day) = Date::fetch(config)?.split();
+    older.sort_unstable_by(|a, b| b.cmp(a));
+    older.dedup();
+    older.truncate(count);
 
-    let disk_dim = config.disk();
-    let tile_size = disk_dim / tile_count;
+    older
+}
 
-    let tiles = (0..tile_count)
-        .flat_map(|x| {
-            (0..tile_count)
-                .map(move |y| (x, y))
+/// Replaces every `{name}` placeholder in `template` with its value.
+fn expand<'a>(template: &str, vars: impl IntoIterator<Item = (&'a str, String)>) -> String {
+    vars.into_iter()
+        .fold(template.to_owned(), |acc, (name, value)| {
+            acc.replace(&format!("{{{name}}}"), &value)
         })
-        .par_bridge()
-        .map(|(x, y)| -> Result<_> {
-            // year:04 i am hilarious
-            let url = format!(
-                "{SLIDER_BASE_URL}/data/imagery/{year:04}/{month:02}/{day:02}/{}---{SLIDER_SECTOR}/{SLIDER_PRODUCT}/{}/{:02}/{x:03}_{y:03}.png",
-                config.satellite.id(),
-                time.as_int(),
-                config.satellite.max_zoom()
-            );
+}
 
-            log::info!("Scraping tile at ({x}, {y}).");
-            
-            let resp = agent
-                .get(&url)
-                .call()?;
-
-            let len: usize = resp.header("Content-Length")
-                .expect("Response header should have Content-Length")
-                .parse()?;
-
-            let reader = resp.into_reader();
-            let dec = png::Decoder::new(reader);
-            let mut reader = dec.read_info()?;
-            let mut buf = config.satellite.tile_image();
-            let info = reader.next_frame(unsafe { buf.buffer_mut() })?;
-            debug_assert!(matches!(info.color_type, png::ColorType::Rgb));
-            let buf = buf.scale::<Lanczos3>(tile_size, tile_size);
-
-            log::info!(
-                "Finished scraping tile at ({x}, {y}). Size: {:.2}KiB",
-                len as f32 / 1024.0
-            );
+impl ImagerySource for Slider {
+    fn select_frame(&self, policy: FramePolicy) -> Result<u64> {
+        let timestamps = Times::fetch(self)?.newest_first();
+        let now = SystemTime::now();
 
-            Ok((x, y, buf))
-        });
-    
-    log::info!("Stitching tiles...");
-    let stitched = Mutex::new(Image::alloc(disk_dim, disk_dim).boxed());
-    tiles.try_for_each(|a|{
-        let (y, x, buf) = a?;
-        // yes, this is possible lockless.
-        // no, i will not do it.
-        // if you do it, construct a sendable pointer, then exclusively use .add and slice::from_raw_parts(_mut)
-        // SAFETY: tiles iterates over the number of tiles, each tile == tile_size, `stitched` is a image of tile_size * tile_count.
-        unsafe { stitched.lock().unwrap_or_else(PoisonError::into_inner).overlay_at(&buf, x * tile_size, y * tile_size) };        
-        anyhow::Ok(())
-    })?;
-
-    Ok(stitched.into_inner().unwrap())
-}
+        let frame = select(policy, &timestamps, now).with_context(|| {
+            format!("None of the {} listed frames match the {policy:?} policy", timestamps.len())
+        })?;
 
-fn composite(config: &Config, source: Image<Box<[u8]>>) -> Result<()> {
-    log::info!("Compositing...");
+        let time = FrameTime::parse(frame)?;
+        let age = time.age(now);
+        let label = humantime::format_duration(Duration::from_secs(age.as_secs() / 60 * 60));
 
-    let disk_dim = config.disk();
+        log::debug!("Selected frame captured {time} ({label} ago.)");
 
-    let composite = if let Some(path) = &config.background_image {
-        static BG: OnceLock<Image<Box<[u8]>>> = OnceLock::new();
+        if age > self.stale_after {
+            log::warn!(
+                "The selected frame was captured {label} ago ({time}) - SLIDER may not be publishing new imagery."
+            );
+        }
 
-        let mut bg = BG.get_or_try_init(|| {
-            use image::io::Reader;
+        Ok(frame)
+    }
 
-            let image = Reader::open(path)
-                .context("Failed to open background image at path {path:?}")?
-                .decode()
-                .context("Failed to load background image - corrupt or unsupported?")?
-                .into_rgb8();
+    fn recent_frames(&self, count: usize) -> Result<Vec<u64>> {
+        let mut timestamps = Times::fetch(self)?.newest_first();
+        timestamps.truncate(count);
 
-            let mut image = Image::build(image.width(), image.height()).buf(image.into_vec().into_boxed_slice());
+        Ok(timestamps)
+    }
 
-            if image.width() != config.resolution_x || 
-               image.height() != config.resolution_y 
-            {
-                log::info!("Resizing background image to fit...");
+    fn frames_before(&self, frame: u64, count: usize) -> Result<Vec<u64>> {
+        // Recent frames are all in the latest times list (which is usually cached)...
+        let mut frames = older(frame, Times::fetch(self)?.newest_first(), count);
+
+        // ...but frames picked by time (with --at or --ago) tend to be older than all of them,
+        // so fall back on the lists for the frame's day and the one before it.
+        if frames.len() < count {
+            let day = FrameTime::parse(frame)?.unix().div_euclid(86_400) * 86_400;
+            let available = Dates::fetch(self)?;
+
+            let times: Vec<_> = [day, day - 86_400]
+                .into_iter()
+                .map(|day| FrameTime::from_unix(day).date())
+                .filter(|date| available.dates.contains(date))
+                .map(|date| TimesByDate::fetch(self, date))
+                .collect::<Result<_>>()?;
+
+            let listed = times.into_iter().flat_map(TimesByDate::into_timestamps);
+            frames = older(frame, frames.into_iter().chain(listed), count);
+        }
 
-                image = image.scale::<Lanczos3>(config.resolution_x, config.resolution_y);
-            }
+        Ok(frames)
+    }
 
-            anyhow::Ok(image)
-        })?.clone();
+    fn frame_near(&self, target: SystemTime) -> Result<u64> {
+        let target = time::to_unix(target);
+        let day = target.div_euclid(86_400) * 86_400;
+        let available = Dates::fetch(self)?;
+
+        // The closest frame may be on the other side of midnight, so check the neighboring days too.
+        let times: Vec<_> = [day - 86_400, day, day + 86_400]
+            .into_iter()
+            .map(|day| FrameTime::from_unix(day).date())
+            .filter(|date| available.dates.contains(date))
+            .map(|date| TimesByDate::fetch(self, date))
+            .collect::<Result<_>>()?;
+
+        times
+            .into_iter()
+            .flat_map(TimesByDate::into_timestamps)
+            .filter_map(|timestamp| FrameTime::parse(timestamp).ok())
+            .min_by_key(|time| time.unix().abs_diff(target))
+            .map(|time| time.timestamp())
+            .context("No frames are available near the requested time")
+    }
 
-        log::info!("Compositing source into destination...");
+    fn fetch(&self, frame: u64, width: u32, height: u32) -> Result<Image<Box<[u8]>>> {
+        download(self, frame, width, height)
+    }
 
-        cutout_disk(
-            bg.as_mut(),
-            source.as_ref(),
-            (config.resolution_x - disk_dim) / 2,
-            (config.resolution_y - disk_dim) / 2
-        );
+    fn geometry(&self) -> Geometry {
+        // A region is a rectangle of its own, and is cut out of the tiles before it's handed over.
+        if let Some(region) = self.region {
+            return Geometry {
+                tile_count: 1,
+                tile_width: region.width().round() as u32,
+                tile_height: region.height().round() as u32,
+                shape: Shape::Rectangle,
+            };
+        }
 
-        bg
+        let (tile_width, tile_height) = self.satellite.tile_size(self.sector);
+
+        Geometry {
+            tile_count: self.satellite.tile_count(self.sector),
+            tile_width,
+            tile_height,
+            shape: if self.sector.is_full_disk() {
+                Shape::Disk
+            } else {
+                Shape::Rectangle
+            },
+        }
     }
-    else {
-        let mut behind = Image::alloc(config.resolution_x, config.resolution_y).boxed();
-
-        unsafe { 
-            behind.overlay_at(
-                &source,
-                (config.resolution_x - disk_dim) / 2,
-                (config.resolution_y - disk_dim) / 2,
-            ) 
-        };
+}
 
-        behind
+fn download(slider: &Slider, time: u64, width: u32, height: u32) -> Result<Image<Box<[u8]>>> {
+    let satellite = slider.satellite;
+    let sector = slider.sector;
+    // Regions are small enough to be worth fetching at full detail.
+    let zoom = match slider.region {
+        Some(_) => satellite.max_zoom(sector),
+        None => pick_zoom(
+            satellite.tile_size(sector),
+            satellite.max_zoom(sector),
+            slider.zoom_bias,
+            (width, height)
+        ),
     };
-    
-    log::info!("Compositing complete.");
 
-    composite.save(
-        config.target_path.join(OUTPUT_NAME)
+    let zoom = match slider.budget.exceeded(&slider.ledger) {
+        None => zoom,
+        Some(reason) => match slider.over_budget {
+            OverBudget::Skip => bail!("Not downloading frame {time}: {reason}"),
+            OverBudget::Downgrade => {
+                log::warn!("The {reason}, falling back to the lowest zoom level.");
+                0
+            }
+        }
+    };
+
+    let tile_count = 1 << zoom;
+    let (tile_width, tile_height) = satellite.tile_size(sector);
+
+    // The tiles to download, and (for a region) where it lies within the mosaic they make up.
+    let (columns, rows, region) = match slider.region {
+        Some(region) => {
+            let region = region_at(region, satellite.tile_count(sector), tile_count);
+            let (columns, rows) = covering_tiles(region, (tile_width, tile_height));
+
+            let region = Bounds {
+                left: region.left - f64::from(columns.start * tile_width),
+                top: region.top - f64::from(rows.start * tile_height),
+                right: region.right - f64::from(columns.start * tile_width),
+                bottom: region.bottom - f64::from(rows.start * tile_height),
+            };
+
+            (columns, rows, Some(region))
+        }
+        None => (0..tile_count, 0..tile_count, None),
+    };
+
+    log::info!(
+        "Downloading at zoom level {zoom} ({}x{} of {tile_count}x{tile_count} tiles.)",
+        columns.len(),
+        rows.len()
     );
 
-    log::info!("Output saved.");
+    // Frames are filed under the (UTC) date they were captured on.
+    let FrameTime { year, month, day, .. } = FrameTime::parse(time)?;
 
-    Ok(())
-}
+    // Tiles are stitched at their native size and the whole mosaic resampled once at the end -
+    // scaling each tile on its own leaves seams at their edges.
+    let (mosaic_width, mosaic_height) = (tile_width * columns.len() as u32, tile_height * rows.len() as u32);
 
-const BLACK: [u8; 3] = [4; 3];
+    // Where the tile in the given column and row goes in the mosaic.
+    let at = |column: u32, row: u32| ((column - columns.start) * tile_width, (row - rows.start) * tile_height);
 
-#[derive(Clone, Copy, Debug)]
-enum Direction {
-    Left,
-    Right
-}
+    let deadline = Instant::now() + slider.frame_deadline;
+
+    let fetch_tile = |row: u32, column: u32| -> Result<_> {
+        let key = TileKey {
+            satellite: satellite.id(),
+            sector: sector.id(),
+            product: slider.product,
+            timestamp: time,
+            zoom,
+            row,
+            column,
+        };
+
+        let (bytes, cached) = match slider.cache.as_ref().and_then(|cache| cache.get(&key)) {
+            Some(bytes) => {
+                log::info!("Loaded tile at row {row}, column {column} from cache.");
+                (bytes, true)
+            }
+            None => {
+                log::info!("Scraping tile at row {row}, column {column}.");
+
+                let what = format!("tile at row {row}, column {column}");
+                let bytes = slider.backoff.run(&what, deadline, |remaining| {
+                    let resp = slider
+                        .get(&slider.tile_path, &[
+                            // year:04 i am hilarious
+                            ("year", format!("{year:04}")),
+                            ("month", format!("{month:02}")),
+                            ("day", format!("{day:02}")),
+                            ("timestamp", time.to_string()),
+                            ("zoom", format!("{zoom:02}")),
+                            ("row", format!("{row:03}")),
+                            ("column", format!("{column:03}")),
+                        ])
+                        .timeout(remaining.min(TIMEOUT))
+                        .call()?;
+
+                    let len = resp.header("Content-Length").map(str::to_owned);
+
+                    match &slider.throttle {
+                        Some(throttle) => tile::read_body(throttle.reader(resp.into_reader()), len.as_deref()),
+                        None => tile::read_body(resp.into_reader(), len.as_deref()),
+                    }
+                })?;
+
+                slider.ledger.record(bytes.len() as u64);
+
+                log::info!(
+                    "Finished scraping tile at row {row}, column {column}. Size: {:.2}KiB",
+                    bytes.len() as f32 / 1024.0
+                );
+
+                (bytes, false)
+            }
+        };
+
+        // Single-band products (like clean IR) are published as grayscale tiles, which decode to gray RGB.
+        let buf = tile::decode(&bytes, (tile_width, tile_height))
+            .with_context(|| format!("Tile at row {row}, column {column} is unusable"))?;
 
-// Identifies the bounds of the Earth in the image
-fn cutout_disk(
-    mut bg: Image<&mut [u8]>,
-    earth: Image<&[u8]>,
-    offset_x: u32,
-    offset_y: u32
-) {
-    // Find the midpoint and max of the edges.
-    let x_max = earth.width() - 1;
-    let y_max = earth.height() - 1;
-    let x_center = x_max / 2;
-    let y_center = y_max / 2;
-
-    let step = |x: &mut u32, direction: Direction| {
-        use Direction::*;
-
-        match direction {
-            Left => *x = x.saturating_sub(1),
-            Right => *x = x.saturating_add(1),
+        // Only cache tiles that decoded, so a bad response isn't served up again forever.
+        if let (false, Some(cache)) = (cached, &slider.cache) {
+            cache.put(&key, &bytes).unwrap_or_else(|err| {
+                log::warn!("Failed to cache tile at row {row}, column {column}: {err}");
+            });
         }
+
+        Ok((buf, bytes.len()))
     };
 
-    // Step linearly through the image pixels until we encounter a non-black pixel,
-    // returning its coordinates.
-    let march = |mut x: u32, y: u32, direction: Direction| -> u32 {        
-        log::debug!("Performing cutout march for direction {direction:?}...");
+    // The last successfully downloaded frame, if it's compatible with this one. It stays put
+    // until this frame succeeds, so it's still there to substitute from if this one fails.
+    let previous = slider.previous
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .clone()
+        .filter(|previous| previous.width() == mosaic_width && previous.height() == mosaic_height);
+    let previous = previous.as_deref();
+
+    // Whether or not the previous frame has any data in the tile at the given position.
+    let previous_has_data = |column: u32, row: u32| previous.as_ref().is_some_and(|previous| {
+        let (left, top) = at(column, row);
+        !is_blank(crop(previous.as_ref(), left, top, tile_width, tile_height).as_ref())
+    });
+
+    let tiles = rows.clone()
+        .flat_map(|row| {
+            columns.clone()
+                .map(move |column| (row, column))
+        })
+        .par_bridge()
+        .map(|(row, column)| (row, column, fetch_tile(row, column)));
+    
+    log::info!("Stitching tiles...");
+    let mut timings = Timings::start();
+    let mut stitched = Image::alloc(mosaic_width, mosaic_height).boxed();
+    let canvas = Canvas::new(&mut stitched);
+
+    let results: Vec<_> = slider.pool.install(|| tiles.map(|(row, column, buf)| {
+        let (buf, len) = buf.and_then(|(buf, len)| {
+            // A blank tile where the last frame had data means SLIDER published an empty placeholder.
+            if is_blank(buf.as_ref()) && previous_has_data(column, row) {
+                bail!("Tile came back blank");
+            }
 
-        loop {
-            // SAFETY: march
-            if unsafe { earth.pixel(x, y) } > BLACK {
-                log::debug!("Found disk bounds at {x}, {y}.");
-                break x
-            };
+            Ok((buf, len))
+        })
+        .map_err(|err| (row, column, err))?;
+
+        // SAFETY: every tile has its own position in the grid, so no two writes overlap.
+        let (left, top) = at(column, row);
+        unsafe { canvas.blit(buf.as_ref(), left, top) };
 
-            step(&mut x, direction);
+        Ok(TileSize { column: column - columns.start, row: row - rows.start, bytes: len })
+    }).collect());
 
-            if x == 0 {
-                log::debug!("Found disk bounds (min) at {x}, {y}.");
-                break x;
+    let mut sizes = Vec::new();
+    let mut failed = Vec::new();
+
+    for result in results {
+        match result {
+            Ok(size) => sizes.push(size),
+            Err(failure) => failed.push(failure),
+        }
+    }
+
+    timings.lap("tiles");
+
+    // Every tile is recorded as it comes in, but the ledger is only saved once per frame.
+    slider.ledger.save();
+
+    if !failed.is_empty() {
+        let total = (columns.len() * rows.len()) as u32;
+        let allowed = (total * slider.max_substituted / 100) as usize;
+
+        failed.sort_unstable_by_key(|(row, column, _)| (*row, *column));
+
+        let positions = failed
+            .iter()
+            .map(|(row, column, _)| format!("row {row}, column {column}"))
+            .collect::<Vec<_>>()
+            .join("; ");
+
+        if failed.len() > allowed {
+            let (_, _, err) = failed.swap_remove(0);
+
+            return Err(err.context(format!(
+                "{} of {total} tiles failed ({positions}), more than the {}% allowed",
+                failed.len() + 1,
+                slider.max_substituted
+            )));
+        }
+
+        for (row, column, err) in &failed {
+            log::warn!("Tile at row {row}, column {column} failed: {err:#}");
+
+            if let Some(previous) = previous {
+                let (left, top) = at(*column, *row);
+                let tile = crop(previous.as_ref(), left, top, tile_width, tile_height);
+                blit(&mut stitched, tile.as_ref(), left, top);
             }
+        }
+
+        if previous.is_some() {
+            log::warn!("Substituted {} of {total} tiles from the previous frame: {positions}", failed.len());
+        } else {
+            log::warn!("Left {} of {total} tiles without data (no previous frame): {positions}", failed.len());
+        }
+    }
 
-            if x > x_max {
-                log::debug!("Found disk bounds (max) at {x}, {y}.");
-                break x.min(x_max);
+    if slider.quality_check {
+        match region {
+            // Regions often run off the edge of the Earth, so the limb is needed to tell space from missing data.
+            Some(_) => {
+                let size = tile_width * tile_count;
+                let limb = satellite.projection().disk(size, size);
+                let (left, top) = (f64::from(columns.start * tile_width), f64::from(rows.start * tile_height));
+
+                quality::inspect_region(
+                    stitched.as_ref(),
+                    Circle { x: limb.x - left, y: limb.y - top, ..limb },
+                    (tile_width, tile_height),
+                    &sizes,
+                    slider.max_missing,
+                )?;
             }
+            None => quality::inspect(
+                stitched.as_ref(),
+                slider.geometry().shape,
+                (tile_width, tile_height),
+                &sizes,
+                slider.max_missing,
+            )?,
         }
+
+        timings.lap("check");
+    }
+
+    // Regions only need the part of the mosaic they cover - the rest of the tiles is overhang.
+    let cutout = region.map(|region| {
+        let (left, top) = (region.left.round() as u32, region.top.round() as u32);
+        let right = (region.right.round() as u32).clamp(left + 1, mosaic_width);
+        let bottom = (region.bottom.round() as u32).clamp(top + 1, mosaic_height);
+
+        crop(stitched.as_ref(), left, top, right - left, bottom - top)
+    });
+
+    let (mosaic_width, mosaic_height, source) = match &cutout {
+        Some(cutout) => (cutout.width(), cutout.height(), cutout),
+        None => (mosaic_width, mosaic_height, &stitched),
     };
 
-    let disk_left = march(0, y_center, Direction::Right);
-    let disk_right = march(x_max, y_center, Direction::Left);
+    let output = if (mosaic_width, mosaic_height) == (width, height) {
+        source.clone()
+    } else {
+        log::debug!("Resampling {mosaic_width}x{mosaic_height} mosaic to {width}x{height}.");
+        let output = source.scale::<Lanczos3>(width, height);
+        timings.lap("resample");
+        output
+    };
 
-    log::debug!("L {disk_left:?} R {disk_right:?}");
+    log::info!("Download timings: {timings}");
 
-    // Approximate the centroid and radius of the circle.
-    let radius = (disk_right - disk_left) / 2;
+    // Substitution works tile by tile, so the previous frame is kept at its native size.
+    *slider.previous.lock().unwrap_or_else(PoisonError::into_inner) = Some(Arc::new(stitched));
 
-    log::debug!("Radius: {radius} Center X: {x_center} Center Y: {y_center}");
+    log::info!(
+        "Downloaded {} today and {} this month.",
+        usage::mib(slider.ledger.today()),
+        usage::mib(slider.ledger.this_month())
+    );
 
-    log::debug!("Starting cutout process...");
+    if let Some(cache) = &slider.cache {
+        cache.evict().unwrap_or_else(|err| {
+            log::warn!("Failed to evict old tiles from the cache: {err}");
+        });
+    }
 
-    let inside = |x: u32| move |y: u32| {
-        ((x_center as i32 - x as i32) * (x_center as i32 - x as i32) + (y_center as i32 - y as i32) * (y_center as i32 - y as i32)).isqrt() < radius as i32
-    };
+    Ok(output)
+}
 
-    for x in 0..earth.width() {
-        for y in 0..earth.height() {
-            if inside(x)(y) {
-                // overlay the earth
-                unsafe { bg.set_pixel(offset_x + x, offset_y + y, earth.pixel(x, y)) };
-            }
-        }
+/// Scales bounds in the full-resolution full disk (a grid of `max_count` tiles across)
+/// to one of `count` tiles across.
+fn region_at(bounds: Bounds, max_count: u32, count: u32) -> Bounds {
+    let scale = f64::from(count) / f64::from(max_count);
+
+    Bounds {
+        left: bounds.left * scale,
+        top: bounds.top * scale,
+        right: bounds.right * scale,
+        bottom: bounds.bottom * scale,
     }
 }
 
-pub fn fetch_latest_timestamp(config: &Config) -> Result<u64> {
-    Ok(Time::fetch(config)?.as_int())   
+/// The columns and rows of tiles that together cover the given bounds.
+fn covering_tiles(bounds: Bounds, (tile_width, tile_height): (u32, u32)) -> (Range<u32>, Range<u32>) {
+    let span = |start: f64, end: f64, size: u32| {
+        let first = (start / f64::from(size)).floor() as u32;
+        let last = (end / f64::from(size)).ceil() as u32;
+
+        first..last.max(first + 1)
+    };
+
+    (
+        span(bounds.left, bounds.right, tile_width),
+        span(bounds.top, bounds.bottom, tile_height),
+    )
 }
 
-#[derive(Debug, Deserialize)]
-struct Time {
-    #[serde(rename = "timestamps_int")]
-    #[serde(deserialize_with = "one")]
-    timestamp: u64
+/// A previously fetched response, along with the validators needed to ask if it has changed.
+#[derive(Debug, Clone)]
+struct Revalidate<T> {
+    etag: Option<String>,
+    last_modified: Option<String>,
+    value: T,
 }
 
-fn one<'de, D>(deserializer: D) -> Result<u64, D::Error>
-where
-    D: serde::Deserializer<'de> {
-    struct Visit;
-    impl<'de> de::Visitor<'de> for Visit {
-        type Value = u64;
+impl<T: Clone> Revalidate<T> {
+    /// The headers that ask the server to only send a response if it's changed since `last`.
+    fn conditions(last: Option<&Self>) -> Vec<(&'static str, &str)> {
+        let Some(last) = last else {
+            return Vec::new();
+        };
+
+        [("If-None-Match", &last.etag), ("If-Modified-Since", &last.last_modified)]
+            .into_iter()
+            .filter_map(|(name, value)| Some((name, value.as_deref()?)))
+            .collect()
+    }
 
-        fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-            write!(f, "array of u64")
+    /// The value to reuse for a response with the given status, or `None` if its body has to be read.
+    fn reuse(last: Option<&Self>, status: u16) -> Option<T> {
+        match (status, last) {
+            (304, Some(last)) => Some(last.value.clone()),
+            _ => None,
         }
+    }
+}
 
-        fn visit_seq<S: de::SeqAccess<'de>>(self, mut seq: S) -> Result<Self::Value, S::Error> {    
-            let value = seq.next_element()?
-                .ok_or(de::Error::custom("empty seq"))?;
-            
-            #[allow(clippy::redundant_pattern_matching)]
-            while let Some(_) = seq.next_element::<u64>()? {}
+/// Every frame listed in `latest_times.json`.
+#[derive(Debug, Clone, Deserialize)]
+struct Times {
+    #[serde(rename = "timestamps_int")]
+    timestamps: Vec<u64>
+}
 
-            Ok(value)
+impl Times {
+    /// Fetches the list, or reuses the last one if the server says it hasn't changed since.
+    /// 
+    /// Since the list is polled every minute around the clock, this makes polling nearly free.
+    pub fn fetch(slider: &Slider) -> Result<Self> {
+        let mut last = slider.latest_times
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+
+        let resp = Revalidate::conditions(last.as_ref())
+            .into_iter()
+            .fold(slider.get(&slider.latest_times_path, &[]), |req, (name, value)| req.set(name, value))
+            .call()?;
+
+        if let Some(times) = Revalidate::reuse(last.as_ref(), resp.status()) {
+            log::debug!("Latest times are unchanged since the last check.");
+            return Ok(times);
         }
+
+        let header = |name: &str| resp.header(name).map(str::to_owned);
+        let (etag, last_modified) = (header("ETag"), header("Last-Modified"));
+        let times: Self = serde_json::from_reader(resp.into_reader())?;
+
+        *last = Some(Revalidate {
+            etag,
+            last_modified,
+            value: times.clone(),
+        });
+
+        Ok(times)
+    }
+
+    /// SLIDER lists the newest frame first, but that's not worth relying on.
+    pub fn newest_first(mut self) -> Vec<u64> {
+        self.timestamps.sort_unstable_by(|a, b| b.cmp(a));
+        self.timestamps
     }
-    deserializer.deserialize_seq(Visit {})
 }
 
+#[derive(Debug, Deserialize)]
+struct Dates {
+    #[serde(rename = "dates_int")]
+    dates: Vec<u64>
+}
 
-impl Time {
-    pub fn fetch(config: &Config) -> Result<Self> {
-        let url = format!(
-            "{SLIDER_BASE_URL}/data/json/{}/{SLIDER_SECTOR}/{SLIDER_PRODUCT}/latest_times.json",
-            config.satellite.id()
-        );
-        
-        let json = ureq::get(&url)
-            .timeout(TIMEOUT)
+impl Dates {
+    pub fn fetch(slider: &Slider) -> Result<Self> {
+        let json = slider
+            .get(&slider.available_dates_path, &[])
             .call()?
             .into_reader();
 
         Ok(serde_json::from_reader(json)?)
     }
-
-    pub fn as_int(&self) -> u64 {
-        self.timestamp
-    }
 }
 
+/// Every frame captured on a given date.
 #[derive(Debug, Deserialize)]
-struct Date {
-    #[serde(rename = "dates_int")]
-    #[serde(deserialize_with = "one")]
-    date: u64
+struct TimesByDate {
+    #[serde(rename = "timestamps_int")]
+    timestamps: Timestamps
 }
 
-impl Date {
-    pub fn fetch(config: &Config) -> Result<Self> {
-        let url = format!(
-            "{SLIDER_BASE_URL}/data/json/{}/{SLIDER_SECTOR}/{SLIDER_PRODUCT}/available_dates.json",
-            config.satellite.id()
-        );
+/// SLIDER groups each day's timestamps by hour, but a flat list is accepted too.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum Timestamps {
+    Flat(Vec<u64>),
+    ByHour(BTreeMap<String, Vec<u64>>)
+}
 
-        let json = ureq::get(&url)
-            .timeout(TIMEOUT)
+impl TimesByDate {
+    pub fn fetch(slider: &Slider, date: u64) -> Result<Self> {
+        let json = slider
+            .get(&slider.times_by_date_path, &[("date", date.to_string())])
             .call()?
             .into_reader();
 
         Ok(serde_json::from_reader(json)?)
     }
 
-    /// Splits date into year, month, and day
-    pub fn split(&self) -> (u16, u8, u8) {
-        let dig = |n: u8| ((self.date / 10u64.pow(u32::from(n))) % 10) as u8;
-        (
-            (u16::from(dig(7)) * 1000) + (u16::from(dig(6)) * 100) + (u16::from(dig(5)) * 10) + u16::from(dig(4)), // yyyy
-            (dig(3) * 10) + dig(2), // mm
-            (dig(1) * 10) + dig(0), // dd
-        )
+    pub fn into_timestamps(self) -> Vec<u64> {
+        match self.timestamps {
+            Timestamps::Flat(list) => list,
+            Timestamps::ByHour(map) => map.into_values().flatten().collect()
+        }
+    }
+}
+
+#[test]
+fn test_expand() {
+    assert_eq!(
+        expand(
+            "data/{satellite}/{x}_{y}/{x}.png",
+            [("satellite", "goes-19".into()), ("x", "001".into()), ("y", "002".into())]
+        ),
+        "data/goes-19/001_002/001.png"
+    );
+}
+
+#[test]
+fn test_pick_zoom() {
+    // GOES full disk: 678px tiles, zoom 0 through 4.
+    assert_eq!(pick_zoom((678, 678), 4, 0, (600, 600)), 0);
+    assert_eq!(pick_zoom((678, 678), 4, 0, (800, 800)), 1);
+    assert_eq!(pick_zoom((678, 678), 4, 0, (2052, 2052)), 2);
+    assert_eq!(pick_zoom((678, 678), 4, 0, (20000, 20000)), 4);
+    assert_eq!(pick_zoom((678, 678), 4, 1, (2052, 2052)), 3);
+    assert_eq!(pick_zoom((678, 678), 4, 9, (2052, 2052)), 4);
+    assert_eq!(pick_zoom((678, 678), 4, -9, (2052, 2052)), 0);
+    // Rectangular tiles have to cover both dimensions.
+    assert_eq!(pick_zoom((625, 375), 4, 0, (1200, 800)), 2);
+}
+
+#[test]
+#[allow(clippy::inconsistent_digit_grouping)]
+fn test_substitution() -> Result<()> {
+    use clap::Parser;
+
+    let dir = std::env::temp_dir().join(format!("satpaper-substitution-test-{}", std::process::id()));
+    let path = |name: &str| dir.join(name).to_string_lossy().into_owned();
+
+    // A 2x2 grid of GOES tiles. Nothing listens on the SLIDER URL, so tiles that aren't cached fail right away.
+    let config = Config::try_parse_from([
+        "satpaper", "-s", "goes-east", "-x", "1356", "-y", "1356", "-d", "100", "-t", ".",
+        "--slider-url", "http://127.0.0.1:1", "--retries", "0", "--max-substituted", "25", "--skip-quality-check",
+        "--cache-dir", &path("cache"), "--usage-file", &path("usage.json"),
+    ])?;
+
+    let slider = Slider::new(&config)?;
+    let cache = slider.cache.as_ref().unwrap();
+
+    let put = |timestamp: u64, row: u32, column: u32, value: u8| -> Result<()> {
+        let mut bytes = Vec::new();
+        let mut enc = png::Encoder::new(&mut bytes, 678, 678);
+        enc.set_color(png::ColorType::Rgb);
+        enc.write_header()?.write_image_data(&vec![value; 678 * 678 * 3])?;
+
+        let key = TileKey {
+            satellite: slider.satellite.id(),
+            sector: slider.sector.id(),
+            product: slider.product,
+            timestamp,
+            zoom: 1,
+            row,
+            column,
+        };
+
+        cache.put(&key, &bytes)
+    };
+
+    let tile = |image: &Image<Box<[u8]>>, row: u32, column: u32| unsafe { image.pixel(column * 678 + 339, row * 678 + 339) }[0];
+
+    for (row, column) in [(0, 0), (0, 1), (1, 0), (1, 1)] {
+        put(2026_10_17_120000, row, column, 100)?;
+    }
+
+    download(&slider, 2026_10_17_120000, 1356, 1356)?;
+
+    // One missing tile (of four) is within the 25% allowed, and is filled in from the previous frame.
+    for (row, column) in [(0, 0), (1, 0), (1, 1)] {
+        put(2026_10_17_121000, row, column, 200)?;
+    }
+
+    let frame = download(&slider, 2026_10_17_121000, 1356, 1356)?;
+    assert_eq!(tile(&frame, 0, 0), 200);
+    assert_eq!(tile(&frame, 0, 1), 100);
+
+    // Two aren't...
+    for (row, column) in [(0, 0), (1, 1)] {
+        put(2026_10_17_122000, row, column, 50)?;
     }
+
+    let err = download(&slider, 2026_10_17_122000, 1356, 1356).unwrap_err();
+    assert!(format!("{err:#}").contains("more than the 25% allowed"), "{err:#}");
+
+    // ...but the rejected frame doesn't cost us the one before it. A blank tile where that frame had data
+    // counts as failed, too.
+    for (row, column) in [(0, 0), (0, 1), (1, 0)] {
+        put(2026_10_17_123000, row, column, 50)?;
+    }
+
+    put(2026_10_17_123000, 1, 1, 0)?;
+
+    let frame = download(&slider, 2026_10_17_123000, 1356, 1356)?;
+    assert_eq!(tile(&frame, 0, 1), 50);
+    assert_eq!(tile(&frame, 1, 1), 200);
+
+    std::fs::remove_dir_all(dir)?;
+
+    Ok(())
+}
+
+#[test]
+fn test_covering_tiles() {
+    let bounds = Bounds { left: 1000.0, top: 700.0, right: 2100.0, bottom: 1356.0 };
+    assert_eq!(covering_tiles(bounds, (678, 678)), (1..4, 1..2));
+
+    // At a lower zoom, the same region covers fewer (but still whole) tiles.
+    let bounds = region_at(bounds, 16, 4);
+    assert_eq!(bounds.left, 250.0);
+    assert_eq!(covering_tiles(bounds, (678, 678)), (0..1, 0..1));
 }
 
 #[test]
 #[allow(clippy::inconsistent_digit_grouping)]
-fn test_date_split() {
-    assert_eq!(Date { date: 2023_10_26 }.split(), (2023, 10, 26));
-    assert_eq!(Date { date: 2027_04_25 }.split(), (2027, 4, 25));
-}
\ No newline at end of file
+fn test_select() {
+    let timestamps = [2026_10_17_120000, 2026_10_17_115000, 2026_10_17_114000];
+    let now = time::from_unix(FrameTime::parse(2026_10_17_121500).unwrap().unix());
+
+    assert_eq!(select(FramePolicy::Newest, &timestamps, now), Some(2026_10_17_120000));
+    assert_eq!(select(FramePolicy::Nth(2), &timestamps, now), Some(2026_10_17_115000));
+    assert_eq!(select(FramePolicy::Nth(4), &timestamps, now), None);
+    assert_eq!(select(FramePolicy::Settled(Duration::from_secs(20 * 60)), &timestamps, now), Some(2026_10_17_115000));
+    assert_eq!(select(FramePolicy::Settled(Duration::from_secs(3600)), &timestamps, now), None);
+    assert_eq!(select(FramePolicy::Newest, &[], now), None);
+}
+
+#[test]
+fn test_older() {
+    assert_eq!(older(5, [1, 7, 4, 5, 2, 4], 2), [4, 2]);
+    assert_eq!(older(5, [1, 2], 10), [2, 1]);
+    assert!(older(1, [1, 2, 3], 10).is_empty());
+}
+
+#[test]
+fn test_revalidate() {
+    let last = |etag: Option<&str>, last_modified: Option<&str>, value: u32| Revalidate {
+        etag: etag.map(str::to_owned),
+        last_modified: last_modified.map(str::to_owned),
+        value,
+    };
+
+    // Nothing to revalidate the first time round, so the list is always fetched in full.
+    assert!(Revalidate::<u32>::conditions(None).is_empty());
+    assert_eq!(Revalidate::<u32>::reuse(None, 304), None);
+    assert_eq!(Revalidate::<u32>::reuse(None, 200), None);
+
+    let both = last(Some("\"v1\""), Some("Sat, 17 Oct 2026 12:00:00 GMT"), 1);
+    assert_eq!(
+        Revalidate::conditions(Some(&both)),
+        [("If-None-Match", "\"v1\""), ("If-Modified-Since", "Sat, 17 Oct 2026 12:00:00 GMT")]
+    );
+
+    let etag_only = last(Some("\"v1\""), None, 1);
+    assert_eq!(Revalidate::conditions(Some(&etag_only)), [("If-None-Match", "\"v1\"")]);
+    assert!(Revalidate::conditions(Some(&last(None, None, 1))).is_empty());
+
+    // Unchanged lists are reused; anything else replaces them.
+    assert_eq!(Revalidate::reuse(Some(&both), 304), Some(1));
+    assert_eq!(Revalidate::reuse(Some(&both), 200), None);
+}
+
+#[test]
+fn test_times_by_date() {
+    let flat: TimesByDate = serde_json::from_str(r#"{"timestamps_int": [2, 1]}"#).unwrap();
+    assert_eq!(flat.into_timestamps(), [2, 1]);
+
+    let by_hour: TimesByDate = serde_json::from_str(r#"{"timestamps_int": {"01": [3], "00": [1, 2]}}"#).unwrap();
+    assert_eq!(by_hour.into_timestamps(), [1, 2, 3]);
+}
@@ -0,0 +1,66 @@
+use std::time::SystemTime;
+
+use anyhow::Result;
+
+use crate::composite::Image;
+use crate::config::FramePolicy;
+
+/// A provider of satellite imagery.
+///
+/// Implementors are responsible for discovering which frames are available
+/// and for producing a stitched image for a given frame.
+/// The update loop and compositor only ever talk to sources through this trait.
+pub trait ImagerySource: Sync {
+    /// Returns the identifier of the recently published frame chosen by the given policy.
+    ///
+    /// Identifiers are opaque, but must change whenever a new frame is published.
+    fn select_frame(&self, policy: FramePolicy) -> Result<u64>;
+
+    /// Returns the identifiers of (up to) the `count` most recent frames, newest first.
+    fn recent_frames(&self, count: usize) -> Result<Vec<u64>>;
+
+    /// Returns the identifiers of (up to) the `count` frames captured just before the given one, newest first.
+    ///
+    /// Unlike [`recent_frames`](Self::recent_frames), this works for frames from any point in time.
+    fn frames_before(&self, frame: u64, count: usize) -> Result<Vec<u64>>;
+
+    /// Returns the identifier of the available frame captured closest to the given time.
+    fn frame_near(&self, time: SystemTime) -> Result<u64>;
+
+    /// Fetches the imagery for the given frame, scaled to `width` by `height` pixels.
+    fn fetch(&self, frame: u64, width: u32, height: u32) -> Result<Image<Box<[u8]>>>;
+
+    /// Describes the layout of the source's native imagery.
+    fn geometry(&self) -> Geometry;
+}
+
+/// The native layout of a source's imagery - a square grid of (possibly rectangular) tiles.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Geometry {
+    /// The number of tiles along each edge of the grid.
+    pub tile_count: u32,
+    /// The width of a single tile, in pixels.
+    pub tile_width: u32,
+    /// The height of a single tile, in pixels.
+    pub tile_height: u32,
+    /// What the imagery depicts, which determines how it is composited.
+    pub shape: Shape,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Shape {
+    /// The full disk of the Earth on a black background.
+    Disk,
+    /// A rectangular region that should fill the entire wallpaper.
+    Rectangle,
+}
+
+impl Geometry {
+    /// The width and height of the full, unscaled image, in pixels.
+    pub fn native_size(&self) -> (u32, u32) {
+        (
+            self.tile_count * self.tile_width,
+            self.tile_count * self.tile_height,
+        )
+    }
+}
@@ -0,0 +1,199 @@
+use std::fmt;
+use std::io::Read;
+
+use image::ImageFormat;
+
+use crate::composite::Image;
+
+/// The largest tile we're willing to download. Real tiles are a few hundred kilobytes at most.
+pub const MAX_TILE_BYTES: u64 = 16 * 1024 * 1024;
+
+const PNG_MAGIC: &[u8] = b"\x89PNG\r\n\x1a\n";
+const JPEG_MAGIC: &[u8] = b"\xff\xd8\xff";
+
+/// A tile that downloaded, but can't be used.
+#[derive(Debug)]
+pub enum TileError {
+    /// The response was bigger than [`MAX_TILE_BYTES`].
+    TooLarge,
+    /// The response wasn't a PNG or JPEG image.
+    UnknownFormat,
+    Png(png::DecodingError),
+    Jpeg(image::ImageError),
+    /// The image decoded fine, but isn't the size tiles are supposed to be.
+    WrongSize {
+        expected: (u32, u32),
+        actual: (u32, u32),
+    },
+}
+
+impl fmt::Display for TileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::TooLarge => write!(f, "Tile is larger than {} bytes", MAX_TILE_BYTES),
+            Self::UnknownFormat => write!(f, "Tile is neither a PNG nor a JPEG image"),
+            Self::Png(err) => write!(f, "Failed to decode PNG tile: {err}"),
+            Self::Jpeg(err) => write!(f, "Failed to decode JPEG tile: {err}"),
+            Self::WrongSize { expected, actual } => write!(
+                f,
+                "Tile is {}x{}, expected {}x{}",
+                actual.0, actual.1, expected.0, expected.1
+            ),
+        }
+    }
+}
+
+impl std::error::Error for TileError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Png(err) => Some(err),
+            Self::Jpeg(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+/// Reads a tile response body, refusing anything larger than [`MAX_TILE_BYTES`].
+///
+/// `Content-Length` is only used as a hint, since chunked responses don't have one.
+pub fn read_body(reader: impl Read, content_length: Option<&str>) -> anyhow::Result<Vec<u8>> {
+    let len = content_length.and_then(|len| len.trim().parse::<u64>().ok());
+
+    if len.is_some_and(|len| len > MAX_TILE_BYTES) {
+        return Err(TileError::TooLarge.into());
+    }
+
+    let mut bytes = Vec::with_capacity(len.unwrap_or(0) as usize);
+    reader.take(MAX_TILE_BYTES + 1).read_to_end(&mut bytes)?;
+
+    if bytes.len() as u64 > MAX_TILE_BYTES {
+        return Err(TileError::TooLarge.into());
+    }
+
+    Ok(bytes)
+}
+
+/// Decodes a PNG or JPEG tile of the given size into RGB.
+///
+/// Any color type and bit depth is accepted; transparent pixels become black, like missing data.
+pub fn decode(bytes: &[u8], expected: (u32, u32)) -> Result<Image<Box<[u8]>>, TileError> {
+    let (actual, rgb) = if bytes.starts_with(PNG_MAGIC) {
+        decode_png(bytes, expected)?
+    } else if bytes.starts_with(JPEG_MAGIC) {
+        let image = image::load_from_memory_with_format(bytes, ImageFormat::Jpeg)
+            .map_err(TileError::Jpeg)?;
+
+        ((image.width(), image.height()), image.into_rgb8().into_raw())
+    } else {
+        return Err(TileError::UnknownFormat);
+    };
+
+    if actual != expected {
+        return Err(TileError::WrongSize { expected, actual });
+    }
+
+    Ok(Image::build(actual.0, actual.1).buf(rgb.into_boxed_slice()))
+}
+
+fn decode_png(bytes: &[u8], expected: (u32, u32)) -> Result<((u32, u32), Vec<u8>), TileError> {
+    use png::{ColorType, Transformations};
+
+    let mut dec = png::Decoder::new(bytes);
+    // Palettes, low bit depths and tRNS chunks are expanded, and 16-bit channels cut down to 8.
+    dec.set_transformations(Transformations::EXPAND | Transformations::STRIP_16);
+
+    let mut reader = dec.read_info().map_err(TileError::Png)?;
+
+    // Check before decoding, so a bogus header can't make us allocate a huge buffer.
+    let actual = (reader.info().width, reader.info().height);
+
+    if actual != expected {
+        return Err(TileError::WrongSize { expected, actual });
+    }
+
+    let mut buf = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buf).map_err(TileError::Png)?;
+    buf.truncate(info.buffer_size());
+
+    let over_black = |v: u8, a: u8| (u16::from(v) * u16::from(a) / 255) as u8;
+
+    let rgb = match info.color_type {
+        ColorType::Rgb => buf,
+        ColorType::Rgba => buf
+            .chunks_exact(4)
+            .flat_map(|px| [px[0], px[1], px[2]].map(|v| over_black(v, px[3])))
+            .collect(),
+        ColorType::Grayscale => buf
+            .into_iter()
+            .flat_map(|v| [v; 3])
+            .collect(),
+        ColorType::GrayscaleAlpha => buf
+            .chunks_exact(2)
+            .flat_map(|px| [over_black(px[0], px[1]); 3])
+            .collect(),
+        // EXPAND always turns palettes into RGB(A).
+        ColorType::Indexed => unreachable!("indexed PNGs are expanded while decoding"),
+    };
+
+    Ok((actual, rgb))
+}
+
+#[test]
+fn test_decode() {
+    use png::{BitDepth, ColorType};
+
+    let encode_png = |color: ColorType, depth: BitDepth, data: &[u8], palette: Option<&[u8]>| {
+        let mut bytes = Vec::new();
+        let mut enc = png::Encoder::new(&mut bytes, 2, 1);
+        enc.set_color(color);
+        enc.set_depth(depth);
+
+        if let Some(palette) = palette {
+            enc.set_palette(palette);
+        }
+
+        enc.write_header().unwrap().write_image_data(data).unwrap();
+        bytes
+    };
+
+    let decoded = |bytes: &[u8]| decode(bytes, (2, 1)).unwrap().bytes().to_vec();
+
+    let rgb = encode_png(ColorType::Rgb, BitDepth::Eight, &[10, 20, 30, 40, 50, 60], None);
+    assert_eq!(decoded(&rgb), [10, 20, 30, 40, 50, 60]);
+
+    let rgba = encode_png(ColorType::Rgba, BitDepth::Eight, &[10, 20, 30, 255, 40, 50, 60, 0], None);
+    assert_eq!(decoded(&rgba), [10, 20, 30, 0, 0, 0]);
+
+    let gray = encode_png(ColorType::Grayscale, BitDepth::Eight, &[7, 200], None);
+    assert_eq!(decoded(&gray), [7, 7, 7, 200, 200, 200]);
+
+    let gray16 = encode_png(ColorType::Grayscale, BitDepth::Sixteen, &[7, 1, 200, 2], None);
+    assert_eq!(decoded(&gray16), [7, 7, 7, 200, 200, 200]);
+
+    let indexed = encode_png(ColorType::Indexed, BitDepth::Eight, &[1, 0], Some(&[1, 2, 3, 4, 5, 6]));
+    assert_eq!(decoded(&indexed), [4, 5, 6, 1, 2, 3]);
+
+    let mut jpeg = Vec::new();
+    image::codecs::jpeg::JpegEncoder::new(&mut jpeg)
+        .encode(&[128; 6], 2, 1, image::ExtendedColorType::Rgb8)
+        .unwrap();
+    assert_eq!(decoded(&jpeg).len(), 6);
+
+    assert!(matches!(decode(&rgb, (4, 4)), Err(TileError::WrongSize { actual: (2, 1), .. })));
+    assert!(matches!(decode(b"<html>", (2, 1)), Err(TileError::UnknownFormat)));
+    assert!(matches!(decode(&rgb[..rgb.len() - 20], (2, 1)), Err(TileError::Png(_))));
+}
+
+#[test]
+fn test_read_body() {
+    let body = vec![1; 1000];
+
+    assert_eq!(read_body(&body[..], None).unwrap(), body);
+    assert_eq!(read_body(&body[..], Some("not a number")).unwrap(), body);
+
+    let too_large = read_body(&body[..], Some(&(MAX_TILE_BYTES + 1).to_string())).unwrap_err();
+    assert!(matches!(too_large.downcast_ref(), Some(TileError::TooLarge)));
+
+    let endless = std::io::repeat(0);
+    assert!(read_body(endless, None).unwrap_err().is::<TileError>());
+}
@@ -0,0 +1,260 @@
+use std::fmt;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{bail, Result};
+
+const SECS_PER_DAY: i64 = 86_400;
+
+// Civil date <-> day count conversions, after Howard Hinnant's `days_from_civil`/`civil_from_days`.
+// http://howardhinnant.github.io/date_algorithms.html
+
+/// Converts a proleptic Gregorian date into days since the Unix epoch.
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let doy = (153 * ((month as i64 + 9) % 12) + 2) / 5 + day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+
+    era * 146_097 + doe - 719_468
+}
+
+/// Converts days since the Unix epoch into a proleptic Gregorian date.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let doe = days.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    (year, month, day)
+}
+
+/// A SLIDER frame timestamp (`YYYYMMDDhhmmss`, in UTC), broken down into its components.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct FrameTime {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+}
+
+impl FrameTime {
+    /// Parses a SLIDER timestamp, rejecting any that don't describe a valid time.
+    pub fn parse(timestamp: u64) -> Result<Self> {
+        let part = |div: u64, modulo: u64| ((timestamp / div) % modulo) as u8;
+
+        let time = Self {
+            year: (timestamp / 10_000_000_000) as u16,
+            month: part(100_000_000, 100),
+            day: part(1_000_000, 100),
+            hour: part(10_000, 100),
+            minute: part(100, 100),
+            second: part(1, 100),
+        };
+
+        let valid = (1..=9999).contains(&time.year)
+            && (1..=12).contains(&time.month)
+            && (1..=days_in_month(time.year as i64, time.month as u32)).contains(&(time.day as u32))
+            && time.hour < 24
+            && time.minute < 60
+            && time.second < 60;
+
+        if !valid {
+            bail!("{timestamp} is not a valid SLIDER timestamp");
+        }
+
+        Ok(time)
+    }
+
+    /// Converts seconds since the Unix epoch into a frame time.
+    pub fn from_unix(secs: i64) -> Self {
+        let (year, month, day) = civil_from_days(secs.div_euclid(SECS_PER_DAY));
+        let secs = secs.rem_euclid(SECS_PER_DAY);
+
+        Self {
+            year: year as u16,
+            month: month as u8,
+            day: day as u8,
+            hour: (secs / 3600) as u8,
+            minute: (secs % 3600 / 60) as u8,
+            second: (secs % 60) as u8,
+        }
+    }
+
+    /// The time as seconds since the Unix epoch.
+    pub fn unix(&self) -> i64 {
+        let days = days_from_civil(self.year as i64, self.month as u32, self.day as u32);
+        let secs = self.hour as i64 * 3600 + self.minute as i64 * 60 + self.second as i64;
+
+        days * SECS_PER_DAY + secs
+    }
+
+    /// The time as a SLIDER `YYYYMMDDhhmmss` timestamp.
+    pub fn timestamp(&self) -> u64 {
+        self.date() * 1_000_000 + self.hour as u64 * 10_000 + self.minute as u64 * 100 + self.second as u64
+    }
+
+    /// The date as a SLIDER `YYYYMMDD` integer.
+    pub fn date(&self) -> u64 {
+        self.year as u64 * 10_000 + self.month as u64 * 100 + self.day as u64
+    }
+
+    /// How long before `now` the frame was captured, rounded down to whole seconds.
+    /// 
+    /// Frames from the future (thanks to clock skew) are zero seconds old.
+    pub fn age(&self, now: SystemTime) -> Duration {
+        Duration::from_secs(to_unix(now).saturating_sub(self.unix()).max(0) as u64)
+    }
+}
+
+impl fmt::Display for FrameTime {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+            self.year, self.month, self.day, self.hour, self.minute, self.second
+        )
+    }
+}
+
+fn days_in_month(year: i64, month: u32) -> u32 {
+    let next = if month == 12 { days_from_civil(year + 1, 1, 1) } else { days_from_civil(year, month + 1, 1) };
+    (next - days_from_civil(year, month, 1)) as u32
+}
+
+pub fn to_unix(time: SystemTime) -> i64 {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(after) => after.as_secs() as i64,
+        Err(before) => -(before.duration().as_secs() as i64),
+    }
+}
+
+pub fn from_unix(secs: i64) -> SystemTime {
+    if secs >= 0 {
+        UNIX_EPOCH + Duration::from_secs(secs as u64)
+    } else {
+        UNIX_EPOCH - Duration::from_secs(secs.unsigned_abs())
+    }
+}
+
+/// Parses a UTC time in the form `YYYY-MM-DDThh:mm[:ss][Z]`.
+///
+/// A space is accepted in place of the `T`, and the time may be omitted entirely (meaning midnight.)
+pub fn parse_utc(s: &str) -> Result<SystemTime, String> {
+    let err = || format!("`{s}` is not a UTC time in the form YYYY-MM-DDThh:mm[:ss]Z");
+
+    let s = s.trim().trim_end_matches(['Z', 'z']);
+
+    let (date, time) = s
+        .split_once(['T', 't', ' '])
+        .unwrap_or((s, "00:00"));
+
+    let num = |s: Option<&str>, len: usize| {
+        s.filter(|s| s.len() == len && s.bytes().all(|b| b.is_ascii_digit()))
+            .and_then(|s| s.parse::<u32>().ok())
+            .ok_or_else(err)
+    };
+
+    let mut date = date.split('-');
+    let year = num(date.next(), 4)?;
+    let month = num(date.next(), 2)?;
+    let day = num(date.next(), 2)?;
+
+    let mut time = time.split(':');
+    let hour = num(time.next(), 2)?;
+    let minute = num(time.next(), 2)?;
+    let second = time.next().map_or(Ok(0), |s| num(Some(s), 2))?;
+
+    if date.next().is_some() || time.next().is_some() {
+        return Err(err());
+    }
+
+    // The month is checked first, since `days_in_month` only makes sense for real ones.
+    if !(1..=12).contains(&month) || !(1..=days_in_month(year as i64, month)).contains(&day) {
+        return Err(err());
+    }
+
+    if hour > 23 || minute > 59 || second > 59 {
+        return Err(err());
+    }
+
+    let days = days_from_civil(year as i64, month, day);
+    let secs = (hour * 3600 + minute * 60 + second) as i64;
+
+    Ok(from_unix(days * SECS_PER_DAY + secs))
+}
+
+/// Parses an HTTP date (RFC 9110's IMF-fixdate), like `Sun, 06 Nov 1994 08:49:37 GMT`.
+pub fn parse_http_date(s: &str) -> Option<SystemTime> {
+    const MONTHS: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+
+    let mut parts = s.split_whitespace().skip(1);
+
+    let day: u32 = parts.next()?.parse().ok()?;
+    let month = parts.next()?;
+    let month = MONTHS.iter().position(|&name| name == month)? as u32 + 1;
+    let year: i64 = parts.next()?.parse().ok()?;
+
+    let mut time = parts.next()?.split(':').map(|part| part.parse::<i64>().ok());
+    let (hour, minute, second) = (time.next()??, time.next()??, time.next()??);
+
+    if parts.next() != Some("GMT") || !(1..=31).contains(&day) || hour > 23 || minute > 59 || second > 60 {
+        return None;
+    }
+
+    Some(from_unix(days_from_civil(year, month, day) * SECS_PER_DAY + hour * 3600 + minute * 60 + second))
+}
+
+#[test]
+#[allow(clippy::inconsistent_digit_grouping)]
+fn test_frame_time() -> Result<()> {
+    assert_eq!(FrameTime::parse(1970_01_01_000000)?.unix(), 0);
+    assert_eq!(FrameTime::parse(2026_09_01_180000)?.unix(), 1_788_285_600);
+    assert_eq!(FrameTime::from_unix(1_788_285_600).timestamp(), 2026_09_01_180000);
+    assert_eq!(FrameTime::from_unix(FrameTime::parse(2024_02_29_235959)?.unix()).timestamp(), 2024_02_29_235959);
+
+    let time = FrameTime::parse(2026_10_17_120000)?;
+    assert_eq!((time.year, time.month, time.day), (2026, 10, 17));
+    assert_eq!(time.date(), 2026_10_17);
+    assert_eq!(time.to_string(), "2026-10-17 12:00:00 UTC");
+    assert_eq!(time.age(from_unix(time.unix() + 900)), Duration::from_secs(900));
+    assert_eq!(time.age(from_unix(time.unix() - 900)), Duration::ZERO);
+
+    assert!(FrameTime::parse(2023_02_29_120000).is_err());
+    assert!(FrameTime::parse(2026_10_17_126000).is_err());
+    assert!(FrameTime::parse(20261017).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_parse_http_date() {
+    assert_eq!(parse_http_date("Tue, 01 Sep 2026 18:00:00 GMT"), Some(from_unix(1_788_285_600)));
+    assert_eq!(parse_http_date("Sun, 06 Nov 1994 08:49:37 GMT"), Some(from_unix(784_111_777)));
+    assert_eq!(parse_http_date("Sun, 06 Nov 1994 08:49:37 PST"), None);
+    assert_eq!(parse_http_date("120"), None);
+}
+
+#[test]
+fn test_parse_utc() {
+    let expected = from_unix(1_788_285_600);
+
+    assert_eq!(parse_utc("2026-09-01T18:00Z"), Ok(expected));
+    assert_eq!(parse_utc("2026-09-01 18:00:00"), Ok(expected));
+    assert_eq!(parse_utc("2026-09-01"), Ok(from_unix(1_788_220_800)));
+    assert!(parse_utc("2026-9-01T18:00Z").is_err());
+    assert!(parse_utc("2026-09-01T25:00Z").is_err());
+
+    // Days that don't exist in their month are rejected, rather than rolling over into the next.
+    assert!(parse_utc("2026-02-31T00:00Z").is_err());
+    assert!(parse_utc("2026-02-29T00:00Z").is_err());
+    assert!(parse_utc("2028-02-29T00:00Z").is_ok());
+    assert!(parse_utc("2026-04-31").is_err());
+}
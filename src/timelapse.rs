@@ -0,0 +1,202 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use image::{Delay, DynamicImage, ExtendedColorType, Frame, RgbImage};
+use image::codecs::gif::{GifEncoder, Repeat};
+use image::codecs::webp::WebPEncoder;
+
+use crate::composite::{render, Image};
+use crate::config::{Config, TimelapseFormat};
+use crate::source::ImagerySource;
+
+const TIMELAPSE_NAME: &str = "satpaper_timelapse";
+
+/// Renders the `count` most recent frames and encodes them into an animation at the target path.
+pub fn export(config: &Config, source: &dyn ImagerySource, count: u32) -> Result<()> {
+    let frames = source.recent_frames(count as usize)?;
+
+    if frames.len() < count as usize {
+        log::warn!("Only {} frames are available for the timelapse.", frames.len());
+    }
+
+    // Oldest first, so the animation plays forwards in time.
+    let rendered: Vec<_> = frames
+        .iter()
+        .rev()
+        .enumerate()
+        .filter_map(|(i, &frame)| {
+            log::info!("Rendering timelapse frame {} of {} ({frame})...", i + 1, frames.len());
+
+            render(config, source, frame)
+                .map_err(|err| log::error!("Failed to render frame {frame}, skipping: {err}"))
+                .ok()
+        })
+        .collect();
+
+    if rendered.is_empty() {
+        bail!("No frames could be rendered for the timelapse");
+    }
+
+    let format = config.timelapse_format;
+    let path = config.target_path.join(
+        format!("{TIMELAPSE_NAME}.{}", format.extension())
+    );
+
+    log::info!("Encoding {} frames as {format:?}...", rendered.len());
+
+    let mut file = BufWriter::new(
+        File::create(&path).with_context(|| format!("Failed to create timelapse at path {path:?}"))?
+    );
+
+    match format {
+        TimelapseFormat::Webp => encode_webp(&mut file, &rendered, config.frame_delay, config.loop_count)?,
+        TimelapseFormat::Apng => encode_apng(&mut file, &rendered, config.frame_delay, config.loop_count)?,
+        TimelapseFormat::Gif => encode_gif(&mut file, &rendered, config.frame_delay, config.loop_count)?,
+    }
+
+    file.flush()?;
+
+    log::info!("Timelapse saved to {path:?}.");
+
+    Ok(())
+}
+
+fn encode_apng(w: impl Write, frames: &[Image<Box<[u8]>>], delay: Duration, loops: u16) -> Result<()> {
+    let (width, height) = (frames[0].width(), frames[0].height());
+
+    let mut enc = png::Encoder::new(w, width, height);
+    enc.set_color(png::ColorType::Rgb);
+    enc.set_depth(png::BitDepth::Eight);
+    enc.set_animated(frames.len() as u32, u32::from(loops))?;
+    enc.set_frame_delay(delay.as_millis().min(u16::MAX as u128) as u16, 1000)?;
+
+    let mut writer = enc.write_header()?;
+
+    for frame in frames {
+        writer.write_image_data(frame.bytes())?;
+    }
+
+    writer.finish()?;
+
+    Ok(())
+}
+
+fn encode_gif(w: impl Write, frames: &[Image<Box<[u8]>>], delay: Duration, loops: u16) -> Result<()> {
+    let mut enc = GifEncoder::new_with_speed(w, 10);
+
+    enc.set_repeat(match loops {
+        0 => Repeat::Infinite,
+        n => Repeat::Finite(n),
+    })?;
+
+    for frame in frames {
+        let rgb = RgbImage::from_raw(frame.width(), frame.height(), frame.bytes().to_vec())
+            .context("Frame buffer does not match its dimensions")?;
+
+        enc.encode_frame(Frame::from_parts(
+            DynamicImage::ImageRgb8(rgb).into_rgba8(),
+            0,
+            0,
+            Delay::from_saturating_duration(delay),
+        ))?;
+    }
+
+    Ok(())
+}
+
+// The `image` crate can only encode still WebPs, so each frame is encoded losslessly on its own
+// and the resulting VP8L bitstreams are stitched into an animated container by hand.
+// https://developers.google.com/speed/webp/docs/riff_container#animation
+fn encode_webp(mut w: impl Write, frames: &[Image<Box<[u8]>>], delay: Duration, loops: u16) -> Result<()> {
+    let (width, height) = (frames[0].width(), frames[0].height());
+    let delay = delay.as_millis().min(0xFF_FFFF) as u32;
+
+    let mut body = Vec::new();
+
+    // Flags (animation only), reserved, then the canvas size.
+    let mut vp8x = vec![0b0000_0010, 0, 0, 0];
+    vp8x.extend(u24(width - 1));
+    vp8x.extend(u24(height - 1));
+    write_chunk(&mut body, b"VP8X", &vp8x);
+
+    // Background color (BGRA), then the loop count.
+    let mut anim = vec![0, 0, 0, 0xFF];
+    anim.extend(loops.to_le_bytes());
+    write_chunk(&mut body, b"ANIM", &anim);
+
+    for frame in frames {
+        let mut still = Vec::new();
+
+        WebPEncoder::new_lossless(&mut still).encode(
+            frame.bytes(),
+            frame.width(),
+            frame.height(),
+            ExtendedColorType::Rgb8,
+        )?;
+
+        // Frame offset, size and duration, then flags (don't blend, don't dispose.)
+        let mut anmf = Vec::new();
+        anmf.extend(u24(0));
+        anmf.extend(u24(0));
+        anmf.extend(u24(frame.width() - 1));
+        anmf.extend(u24(frame.height() - 1));
+        anmf.extend(u24(delay));
+        anmf.push(0b0000_0010);
+        anmf.extend(find_chunk(&still, b"VP8L").context("Encoded WebP frame has no VP8L chunk")?);
+
+        write_chunk(&mut body, b"ANMF", &anmf);
+    }
+
+    w.write_all(b"RIFF")?;
+    w.write_all(&(body.len() as u32 + 4).to_le_bytes())?;
+    w.write_all(b"WEBP")?;
+    w.write_all(&body)?;
+
+    Ok(())
+}
+
+fn u24(n: u32) -> [u8; 3] {
+    let [a, b, c, _] = n.to_le_bytes();
+    [a, b, c]
+}
+
+fn write_chunk(out: &mut Vec<u8>, fourcc: &[u8; 4], payload: &[u8]) {
+    out.extend(fourcc);
+    out.extend((payload.len() as u32).to_le_bytes());
+    out.extend(payload);
+
+    // Chunks are padded to an even length.
+    if payload.len() % 2 == 1 {
+        out.push(0);
+    }
+}
+
+/// Returns the first chunk (header and padding included) with the given FourCC in a RIFF file.
+fn find_chunk<'a>(riff: &'a [u8], fourcc: &[u8; 4]) -> Option<&'a [u8]> {
+    let mut offset = 12;
+
+    while offset + 8 <= riff.len() {
+        let size = u32::from_le_bytes(riff[offset + 4..offset + 8].try_into().ok()?) as usize;
+        let end = (offset + 8 + size + size % 2).min(riff.len());
+
+        if &riff[offset..offset + 4] == fourcc {
+            return Some(&riff[offset..end]);
+        }
+
+        offset = end;
+    }
+
+    None
+}
+
+#[test]
+fn test_find_chunk() {
+    let mut riff = b"RIFF\0\0\0\0WEBP".to_vec();
+    write_chunk(&mut riff, b"VP8X", &[1, 2, 3]);
+    write_chunk(&mut riff, b"VP8L", &[4, 5]);
+
+    assert_eq!(find_chunk(&riff, b"VP8L"), Some(&b"VP8L\x02\0\0\0\x04\x05"[..]));
+    assert_eq!(find_chunk(&riff, b"ALPH"), None);
+}
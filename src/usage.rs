@@ -0,0 +1,309 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::io::{ErrorKind, Read};
+use std::path::PathBuf;
+use std::sync::{Mutex, PoisonError};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::sleep;
+use std::time::{Duration, Instant, SystemTime};
+
+use anyhow::{bail, Context, Result};
+
+use crate::config::Config;
+use crate::time::{self, FrameTime};
+
+/// How many days of history the ledger keeps.
+const HISTORY_DAYS: usize = 400;
+
+/// A persistent record of how many bytes have been downloaded on each (UTC) day.
+pub struct Ledger {
+    path: Option<PathBuf>,
+    days: Mutex<BTreeMap<u64, u64>>,
+    /// Whether anything has been recorded since the ledger was last saved.
+    unsaved: AtomicBool,
+}
+
+impl Ledger {
+    /// Loads the ledger at the given path, starting a fresh one if it doesn't exist yet.
+    ///
+    /// Without a path, usage is only tracked for as long as Satpaper runs.
+    pub fn load(path: Option<PathBuf>) -> Result<Self> {
+        let days = match path.as_ref().map(fs::read_to_string) {
+            Some(Ok(json)) => serde_json::from_str(&json)
+                .with_context(|| format!("Corrupt usage ledger at path {:?}", path.as_ref().unwrap()))?,
+            Some(Err(err)) if err.kind() != ErrorKind::NotFound => {
+                return Err(err).with_context(|| format!("Failed to read usage ledger at path {path:?}"));
+            }
+            _ => BTreeMap::new(),
+        };
+
+        Ok(Self {
+            path,
+            days: Mutex::new(days),
+            unsaved: AtomicBool::new(false),
+        })
+    }
+
+    /// Adds `bytes` to today's total.
+    ///
+    /// This is called for every tile, so it doesn't touch the disk - see [`save`](Self::save).
+    pub fn record(&self, bytes: u64) {
+        let today = current_date();
+        let mut days = self.days.lock().unwrap_or_else(PoisonError::into_inner);
+
+        *days.entry(today).or_default() += bytes;
+
+        while days.len() > HISTORY_DAYS {
+            days.pop_first();
+        }
+
+        self.unsaved.store(true, Ordering::Relaxed);
+    }
+
+    /// Saves the ledger, if anything has been recorded since it was last saved.
+    pub fn save(&self) {
+        if !self.unsaved.swap(false, Ordering::Relaxed) {
+            return;
+        }
+
+        // Other threads can keep recording while the file is written.
+        let days = self.days.lock().unwrap_or_else(PoisonError::into_inner).clone();
+
+        if let Err(err) = self.write(&days) {
+            log::warn!("Failed to save usage ledger: {err}");
+            self.unsaved.store(true, Ordering::Relaxed);
+        }
+    }
+
+    fn write(&self, days: &BTreeMap<u64, u64>) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        // Same trick as the tile cache - never leave a truncated ledger behind.
+        let temp = path.with_extension("part");
+        fs::write(&temp, serde_json::to_vec(days)?)?;
+        fs::rename(&temp, path)?;
+
+        Ok(())
+    }
+
+    /// The number of bytes downloaded today.
+    pub fn today(&self) -> u64 {
+        self.since(current_date())
+    }
+
+    /// The number of bytes downloaded so far this month.
+    pub fn this_month(&self) -> u64 {
+        self.since(current_date() / 100 * 100)
+    }
+
+    /// The number of bytes downloaded on or after the given `YYYYMMDD` date.
+    fn since(&self, date: u64) -> u64 {
+        self.days
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .range(date..)
+            .map(|(_, bytes)| bytes)
+            .sum()
+    }
+}
+
+/// Today's (UTC) date as a `YYYYMMDD` integer.
+fn current_date() -> u64 {
+    FrameTime::from_unix(time::to_unix(SystemTime::now())).date()
+}
+
+pub fn mib(bytes: u64) -> String {
+    format!("{:.2}MiB", bytes as f64 / 1048576.0)
+}
+
+/// Prints how much has been downloaded today and this month (and how that compares to
+/// any budgets), for `--usage`.
+pub fn report(config: &Config) -> Result<()> {
+    let Some(path) = config.usage_file.clone().or_else(default_path) else {
+        bail!("Could not determine where the usage ledger is; pass --usage-file");
+    };
+
+    let ledger = Ledger::load(Some(path.clone()))?;
+
+    println!("Usage ledger: {}", path.display());
+    println!("{}", summary(&ledger, Budget::from_config(config)));
+
+    Ok(())
+}
+
+fn summary(ledger: &Ledger, budget: Budget) -> String {
+    let line = |period: &str, used: u64, limit: Option<u64>| match limit {
+        Some(limit) => format!("{period}: {} of {} ({:.0}%)", mib(used), mib(limit), used as f64 * 100.0 / limit as f64),
+        None => format!("{period}: {}", mib(used)),
+    };
+
+    [
+        line("Today", ledger.today(), budget.daily),
+        line("This month", ledger.this_month(), budget.monthly),
+    ]
+    .join("\n")
+}
+
+/// Daily and monthly limits on downloaded data.
+#[derive(Debug, Clone, Copy)]
+pub struct Budget {
+    pub daily: Option<u64>,
+    pub monthly: Option<u64>,
+}
+
+impl Budget {
+    /// The budgets from --daily-budget and --monthly-budget, in bytes.
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            daily: config.daily_budget.map(|mb| mb * 1024 * 1024),
+            monthly: config.monthly_budget.map(|mb| mb * 1024 * 1024),
+        }
+    }
+
+    /// Describes which limit (if any) the ledger has reached.
+    pub fn exceeded(&self, ledger: &Ledger) -> Option<String> {
+        let over = |limit: Option<u64>, used: u64| limit.filter(|&limit| used >= limit);
+
+        if let Some(limit) = over(self.daily, ledger.today()) {
+            return Some(format!("daily budget of {} is used up", mib(limit)));
+        }
+
+        if let Some(limit) = over(self.monthly, ledger.this_month()) {
+            return Some(format!("monthly budget of {} is used up", mib(limit)));
+        }
+
+        None
+    }
+}
+
+/// A bandwidth limit shared between every concurrent download.
+pub struct Throttle {
+    bytes_per_sec: u64,
+    next: Mutex<Instant>,
+}
+
+impl Throttle {
+    pub fn new(bytes_per_sec: u64) -> Self {
+        Self {
+            bytes_per_sec,
+            next: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Blocks until `bytes` more can be transferred without exceeding the limit.
+    pub fn consume(&self, bytes: usize) {
+        let wait = {
+            let mut next = self.next.lock().unwrap_or_else(PoisonError::into_inner);
+            let now = Instant::now();
+
+            *next = (*next).max(now) + Duration::from_secs_f64(bytes as f64 / self.bytes_per_sec as f64);
+            *next - now
+        };
+
+        sleep(wait);
+    }
+
+    /// Wraps a reader so that everything read through it counts against the limit.
+    pub fn reader<R: Read>(&self, inner: R) -> Throttled<'_, R> {
+        Throttled {
+            inner,
+            throttle: self,
+        }
+    }
+}
+
+pub struct Throttled<'a, R> {
+    inner: R,
+    throttle: &'a Throttle,
+}
+
+impl<R: Read> Read for Throttled<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.throttle.consume(read);
+        Ok(read)
+    }
+}
+
+/// Where the ledger lives by default - a "satpaper" directory inside the platform's per-user data directory.
+pub fn default_path() -> Option<PathBuf> {
+    let home = || env::var_os("HOME").map(PathBuf::from);
+
+    let base = match env::consts::OS {
+        "windows" => env::var_os("APPDATA").map(PathBuf::from),
+        "macos" => home().map(|home| home.join("Library/Application Support")),
+        _ => env::var_os("XDG_DATA_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| home().map(|home| home.join(".local/share"))),
+    };
+
+    base.map(|base| base.join("satpaper").join("usage.json"))
+}
+
+#[test]
+fn test_ledger() -> Result<()> {
+    let path = env::temp_dir().join(format!("satpaper-usage-test-{}.json", std::process::id()));
+
+    let ledger = Ledger::load(Some(path.clone()))?;
+    ledger.record(1000);
+    ledger.record(500);
+
+    // Nothing is written until the ledger is saved, and then only once.
+    assert!(!path.exists());
+    ledger.save();
+    assert!(path.exists());
+    assert!(!ledger.unsaved.load(Ordering::Relaxed));
+
+    // An old day that only counts towards history.
+    ledger.days.lock().unwrap().insert(19700101, 7);
+
+    assert_eq!(ledger.today(), 1500);
+    assert_eq!(ledger.this_month(), 1500);
+
+    let budget = |daily, monthly| Budget { daily, monthly };
+    assert!(budget(None, None).exceeded(&ledger).is_none());
+    assert!(budget(Some(2000), Some(1500)).exceeded(&ledger).unwrap().contains("monthly"));
+    assert!(budget(Some(1500), None).exceeded(&ledger).unwrap().contains("daily"));
+
+    // The old day was inserted behind the ledger's back, so it was never saved.
+    let reloaded = Ledger::load(Some(path.clone()))?;
+    assert_eq!(reloaded.today(), 1500);
+    assert_eq!(reloaded.days.lock().unwrap().len(), 1);
+
+    fs::remove_file(path)?;
+
+    Ok(())
+}
+
+#[test]
+fn test_summary() {
+    let ledger = Ledger::load(None).unwrap();
+    ledger.record(3 * 1024 * 1024);
+
+    let budget = Budget { daily: Some(12 * 1024 * 1024), monthly: None };
+
+    assert_eq!(
+        summary(&ledger, budget),
+        "Today: 3.00MiB of 12.00MiB (25%)\nThis month: 3.00MiB"
+    );
+}
+
+#[test]
+fn test_throttle() {
+    let throttle = Throttle::new(10_000);
+    let start = Instant::now();
+
+    let mut bytes = Vec::new();
+    throttle.reader(&[0; 2_000][..]).read_to_end(&mut bytes).unwrap();
+
+    assert_eq!(bytes.len(), 2_000);
+    assert!(start.elapsed() >= Duration::from_millis(200));
+}